@@ -0,0 +1,443 @@
+use crate::block_ciphers::BlockCipher;
+use crate::secret;
+use crate::Error;
+
+pub const BLOCK_SIZE: usize = 16;
+
+// FIPS-197 AES with 128, 192 or 256 bit keys. The state is a plain 16 byte
+// array in input order, so column c is bytes 4c..4c + 4.
+//
+// Written to be read and poked at rather than to be fast or safe against
+// side channels: the S-box is a lookup table indexed by secret data.
+#[derive(Clone)]
+pub struct Aes {
+    round_keys: Vec<[u8; BLOCK_SIZE]>,
+}
+
+// One step of the cipher, named as in the FIPS-197 Appendix C traces
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Input,
+    SubBytes,
+    ShiftRows,
+    MixColumns,
+    AddRoundKey,
+    // The inverse cipher's steps, in the order it applies them
+    InvShiftRows,
+    InvSubBytes,
+    InvMixColumns,
+}
+
+// The state after `step` of `round`. Round 0 is the initial AddRoundKey.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub round: usize,
+    pub step: Step,
+    pub state: [u8; BLOCK_SIZE],
+}
+
+impl Aes {
+    pub fn new(key: impl AsRef<[u8]>) -> Result<Self, Error> {
+        let key = key.as_ref();
+        if !matches!(key.len(), 16 | 24 | 32) {
+            return Err(Error::InvalidKeySize(key.len()));
+        }
+
+        Ok(Aes {
+            round_keys: expand_key(key),
+        })
+    }
+
+    // 10, 12 or 14 for 128, 192 and 256 bit keys
+    pub fn rounds(&self) -> usize {
+        self.round_keys.len() - 1
+    }
+
+    // The expanded key schedule, one 16 byte key per round plus the initial
+    // whitening key
+    pub fn round_keys(&self) -> &[[u8; BLOCK_SIZE]] {
+        &self.round_keys
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; BLOCK_SIZE]) {
+        self.cipher(block, |_, _, _| {});
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; BLOCK_SIZE]) {
+        self.inv_cipher(block, |_, _, _| {});
+    }
+
+    // Every intermediate state of encrypting `block`
+    pub fn trace_encrypt(&self, block: &[u8; BLOCK_SIZE]) -> Vec<TraceEntry> {
+        let mut state = *block;
+        let mut trace = vec![];
+        self.cipher(&mut state, |round, step, state| {
+            trace.push(TraceEntry {
+                round,
+                step,
+                state: *state,
+            })
+        });
+        trace
+    }
+
+    // Every intermediate state of decrypting `block`. Rounds count down, so
+    // the state after round r here matches the state before round r of
+    // encryption.
+    pub fn trace_decrypt(&self, block: &[u8; BLOCK_SIZE]) -> Vec<TraceEntry> {
+        let mut state = *block;
+        let mut trace = vec![];
+        self.inv_cipher(&mut state, |round, step, state| {
+            trace.push(TraceEntry {
+                round,
+                step,
+                state: *state,
+            })
+        });
+        trace
+    }
+
+    fn cipher<F: FnMut(usize, Step, &[u8; BLOCK_SIZE])>(
+        &self,
+        state: &mut [u8; BLOCK_SIZE],
+        mut record: F,
+    ) {
+        let rounds = self.rounds();
+        record(0, Step::Input, state);
+        add_round_key(state, &self.round_keys[0]);
+        record(0, Step::AddRoundKey, state);
+
+        for round in 1..=rounds {
+            sub_bytes(state, &SBOX);
+            record(round, Step::SubBytes, state);
+            shift_rows(state);
+            record(round, Step::ShiftRows, state);
+            if round != rounds {
+                mix_columns(state);
+                record(round, Step::MixColumns, state);
+            }
+            add_round_key(state, &self.round_keys[round]);
+            record(round, Step::AddRoundKey, state);
+        }
+    }
+
+    fn inv_cipher<F: FnMut(usize, Step, &[u8; BLOCK_SIZE])>(
+        &self,
+        state: &mut [u8; BLOCK_SIZE],
+        mut record: F,
+    ) {
+        let rounds = self.rounds();
+        record(rounds, Step::Input, state);
+        add_round_key(state, &self.round_keys[rounds]);
+        record(rounds, Step::AddRoundKey, state);
+
+        for round in (0..rounds).rev() {
+            inv_shift_rows(state);
+            record(round, Step::InvShiftRows, state);
+            sub_bytes(state, &INV_SBOX);
+            record(round, Step::InvSubBytes, state);
+            add_round_key(state, &self.round_keys[round]);
+            record(round, Step::AddRoundKey, state);
+            if round != 0 {
+                inv_mix_columns(state);
+                record(round, Step::InvMixColumns, state);
+            }
+        }
+    }
+}
+
+impl BlockCipher for Aes {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &mut [u8]) {
+        let block: &mut [u8; BLOCK_SIZE] = block.try_into().expect("AES blocks are 16 bytes");
+        Aes::encrypt_block(self, block);
+    }
+
+    fn decrypt_block(&self, block: &mut [u8]) {
+        let block: &mut [u8; BLOCK_SIZE] = block.try_into().expect("AES blocks are 16 bytes");
+        Aes::decrypt_block(self, block);
+    }
+}
+
+impl Drop for Aes {
+    fn drop(&mut self) {
+        secret::wipe(self.round_keys.as_flattened_mut());
+    }
+}
+
+// Never print key material
+impl std::fmt::Debug for Aes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Aes {{ rounds: {} }}", self.rounds())
+    }
+}
+
+fn expand_key(key: &[u8]) -> Vec<[u8; BLOCK_SIZE]> {
+    let nk = key.len() / 4;
+    let rounds = nk + 6;
+    let total = 4 * (rounds + 1);
+
+    let mut words: Vec<[u8; 4]> = key.chunks(4).map(|w| [w[0], w[1], w[2], w[3]]).collect();
+    let mut rcon = 1u8;
+    for i in nk..total {
+        let mut temp = words[i - 1];
+        if i % nk == 0 {
+            temp.rotate_left(1);
+            temp = temp.map(|b| SBOX[b as usize]);
+            temp[0] ^= rcon;
+            rcon = xtime(rcon);
+        } else if nk > 6 && i % nk == 4 {
+            temp = temp.map(|b| SBOX[b as usize]);
+        }
+        let previous = words[i - nk];
+        words.push([
+            previous[0] ^ temp[0],
+            previous[1] ^ temp[1],
+            previous[2] ^ temp[2],
+            previous[3] ^ temp[3],
+        ]);
+    }
+
+    words
+        .chunks(4)
+        .map(|round| {
+            let mut round_key = [0u8; BLOCK_SIZE];
+            for (column, word) in round.iter().enumerate() {
+                round_key[4 * column..4 * column + 4].copy_from_slice(word);
+            }
+            round_key
+        })
+        .collect()
+}
+
+fn add_round_key(state: &mut [u8; BLOCK_SIZE], round_key: &[u8; BLOCK_SIZE]) {
+    for (byte, key) in state.iter_mut().zip(round_key) {
+        *byte ^= key;
+    }
+}
+
+fn sub_bytes(state: &mut [u8; BLOCK_SIZE], sbox: &[u8; 256]) {
+    for byte in state.iter_mut() {
+        *byte = sbox[*byte as usize];
+    }
+}
+
+// Row r moves r columns to the left
+fn shift_rows(state: &mut [u8; BLOCK_SIZE]) {
+    let old = *state;
+    for column in 0..4 {
+        for row in 0..4 {
+            state[4 * column + row] = old[4 * ((column + row) % 4) + row];
+        }
+    }
+}
+
+fn inv_shift_rows(state: &mut [u8; BLOCK_SIZE]) {
+    let old = *state;
+    for column in 0..4 {
+        for row in 0..4 {
+            state[4 * ((column + row) % 4) + row] = old[4 * column + row];
+        }
+    }
+}
+
+fn mix_columns(state: &mut [u8; BLOCK_SIZE]) {
+    for column in state.chunks_mut(4) {
+        let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+        column[0] = gmul(a, 2) ^ gmul(b, 3) ^ c ^ d;
+        column[1] = a ^ gmul(b, 2) ^ gmul(c, 3) ^ d;
+        column[2] = a ^ b ^ gmul(c, 2) ^ gmul(d, 3);
+        column[3] = gmul(a, 3) ^ b ^ c ^ gmul(d, 2);
+    }
+}
+
+fn inv_mix_columns(state: &mut [u8; BLOCK_SIZE]) {
+    for column in state.chunks_mut(4) {
+        let [a, b, c, d] = [column[0], column[1], column[2], column[3]];
+        column[0] = gmul(a, 14) ^ gmul(b, 11) ^ gmul(c, 13) ^ gmul(d, 9);
+        column[1] = gmul(a, 9) ^ gmul(b, 14) ^ gmul(c, 11) ^ gmul(d, 13);
+        column[2] = gmul(a, 13) ^ gmul(b, 9) ^ gmul(c, 14) ^ gmul(d, 11);
+        column[3] = gmul(a, 11) ^ gmul(b, 13) ^ gmul(c, 9) ^ gmul(d, 14);
+    }
+}
+
+// Multiplication by x in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1
+const fn xtime(a: u8) -> u8 {
+    (a << 1) ^ (((a >> 7) & 1) * 0x1b)
+}
+
+const fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+// The S-box is built rather than pasted: the multiplicative inverse in
+// GF(2^8) (with 0 mapping to 0), then the FIPS-197 affine transform
+const SBOX: [u8; 256] = build_sbox();
+const INV_SBOX: [u8; 256] = invert(&SBOX);
+
+const fn build_sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        // a^254 is a's inverse, as the multiplicative group has order 255
+        let a = i as u8;
+        let mut inverse = 1u8;
+        let mut power = 0;
+        while power < 254 {
+            inverse = gmul(inverse, a);
+            power += 1;
+        }
+        if a == 0 {
+            inverse = 0;
+        }
+
+        sbox[i] = inverse
+            ^ inverse.rotate_left(1)
+            ^ inverse.rotate_left(2)
+            ^ inverse.rotate_left(3)
+            ^ inverse.rotate_left(4)
+            ^ 0x63;
+        i += 1;
+    }
+    sbox
+}
+
+const fn invert(sbox: &[u8; 256]) -> [u8; 256] {
+    let mut inverse = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inverse[sbox[i] as usize] = i as u8;
+        i += 1;
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    fn block(hex: &str) -> Result<[u8; BLOCK_SIZE], Error> {
+        let bytes = hex::decode(hex)?;
+        let mut block = [0u8; BLOCK_SIZE];
+        block.copy_from_slice(&bytes);
+        Ok(block)
+    }
+
+    #[test]
+    fn test_fips197_vectors() -> Result<(), Error> {
+        // Appendix C: the same plaintext under each key size
+        let plaintext = block("00112233445566778899aabbccddeeff")?;
+        let key: Vec<u8> = (0..32).collect();
+        let vectors = [
+            (16, 10, "69c4e0d86a7b0430d8cdb78070b4c55a"),
+            (24, 12, "dda97ca4864cdfe06eaf70a0ec0d7191"),
+            (32, 14, "8ea2b7ca516745bfeafc49904b496089"),
+        ];
+        for (key_len, rounds, expected) in vectors {
+            let aes = Aes::new(&key[..key_len])?;
+            assert_eq!(aes.rounds(), rounds);
+
+            let mut state = plaintext;
+            aes.encrypt_block(&mut state);
+            assert_eq!(hex::encode(&state), expected);
+            aes.decrypt_block(&mut state);
+            assert_eq!(state, plaintext);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_schedule() -> Result<(), Error> {
+        // Appendix A.1
+        let aes = Aes::new(hex::decode("2b7e151628aed2a6abf7158809cf4f3c")?)?;
+        assert_eq!(aes.round_keys().len(), 11);
+        assert_eq!(
+            hex::encode(&aes.round_keys()[1]),
+            "a0fafe1788542cb123a339392a6c7605"
+        );
+        assert_eq!(
+            hex::encode(&aes.round_keys()[10]),
+            "d014f9a8c9ee2589e13f0cc8b6630ca6"
+        );
+
+        assert_eq!(SBOX[0x00], 0x63);
+        assert_eq!(SBOX[0x53], 0xed);
+        assert_eq!(INV_SBOX[0x63], 0x00);
+
+        assert!(matches!(
+            Aes::new([0u8; 20]),
+            Err(Error::InvalidKeySize(20))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace() -> Result<(), Error> {
+        // Appendix C.1, round 1
+        let key: Vec<u8> = (0..16).collect();
+        let aes = Aes::new(&key)?;
+        let plaintext = block("00112233445566778899aabbccddeeff")?;
+        let trace = aes.trace_encrypt(&plaintext);
+
+        let round_one: Vec<String> = trace
+            .iter()
+            .filter(|entry| entry.round == 1)
+            .map(|entry| hex::encode(&entry.state))
+            .collect();
+        assert_eq!(
+            round_one,
+            vec![
+                "63cab7040953d051cd60e0e7ba70e18c",
+                "6353e08c0960e104cd70b751bacad0e7",
+                "5f72641557f5bc92f7be3b291db9f91a",
+                "89d810e8855ace682d1843d8cb128fe4",
+            ]
+        );
+
+        // The last round has no MixColumns
+        let last = trace.last().unwrap();
+        assert_eq!((last.round, last.step), (10, Step::AddRoundKey));
+        assert!(!trace
+            .iter()
+            .any(|entry| entry.round == 10 && entry.step == Step::MixColumns));
+
+        let decrypted = aes.trace_decrypt(&last.state);
+        assert_eq!(decrypted.last().unwrap().state, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_openssl() -> Result<(), Error> {
+        use openssl::symm::Cipher;
+
+        let ciphers = [
+            Cipher::aes_128_ecb(),
+            Cipher::aes_192_ecb(),
+            Cipher::aes_256_ecb(),
+        ];
+        for cipher in ciphers {
+            for _ in 0..16 {
+                let key: Vec<u8> = (0..cipher.key_len()).map(|_| rand::random()).collect();
+                let plaintext: [u8; BLOCK_SIZE] = rand::random();
+
+                let expected = openssl::symm::encrypt(cipher, &key, None, &plaintext).unwrap();
+                let mut state = plaintext;
+                Aes::new(&key)?.encrypt_block(&mut state);
+                assert_eq!(state, expected[..BLOCK_SIZE]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,162 +1,420 @@
 use std::collections::VecDeque;
 
+use crate::aes::Aes;
 use crate::conversions::base64_to_bytes;
-use crate::utility;
-use crate::xor::fixed_xor;
+use crate::hexdump;
+use crate::padding::{Padding, Pkcs7};
+use crate::secret::Secret;
+use crate::xor::xor_into;
+use crate::Error;
+
+pub fn generate_random_aeskey(key_len_bytes: usize) -> Result<Secret, Error> {
+    Secret::random(key_len_bytes)
+}
+
+// A keyed block cipher. Modes call these with slices exactly block_size()
+// bytes long.
+pub trait BlockCipher {
+    fn block_size(&self) -> usize;
+    fn encrypt_block(&self, block: &mut [u8]);
+    fn decrypt_block(&self, block: &mut [u8]);
+}
 
-pub fn generate_random_aeskey(key_len_bytes: usize) -> Result<Vec<u8>, &'static str> {
-    let mut buf = vec![0; key_len_bytes];
-    if openssl::rand::rand_bytes(buf.as_mut_slice()).is_err() {
-        return Err("Could not generate random key bytes!");
+pub fn ecb_encrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    let mut output = padding.pad(input, cipher.block_size())?;
+    for block in output.chunks_mut(cipher.block_size()) {
+        cipher.encrypt_block(block);
     }
-    Ok(buf)
+    Ok(output)
 }
 
-pub fn simple_ecb_encrypt(input: &[u8], key: &[u8]) -> Result<Vec<u8>, &'static str> {
-    let ciphertext =
-        openssl::symm::encrypt(openssl::symm::Cipher::aes_128_ecb(), key, None, input).unwrap();
-    Ok(ciphertext)
+pub fn ecb_decrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    check_block_aligned(input, cipher.block_size())?;
+
+    let mut output = Vec::from(input);
+    for block in output.chunks_mut(cipher.block_size()) {
+        cipher.decrypt_block(block);
+    }
+    padding.unpad(&output, cipher.block_size())
 }
 
-pub fn simple_cbc_encrypt(input: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, &'static str> {
-    let mut plaintext = Vec::from(input);
+// The IV is one block, and should be unpredictable for each message
+pub fn cbc_encrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
+
+    let mut output = padding.pad(input, cipher.block_size())?;
+    let mut previous = Vec::from(iv);
+    for block in output.chunks_mut(cipher.block_size()) {
+        xor_into(block, &previous)?;
+        cipher.encrypt_block(block);
+        previous.copy_from_slice(block);
+    }
+    Ok(output)
+}
 
-    // Pad plaintext to make it an integer number of 16B blocks
-    plaintext = utility::pkcs7_padding(&plaintext, 16).unwrap();
-    println!("Plaintext len: {}", plaintext.len());
-    println!("\tnum blocks:  {}", plaintext.len() / 16);
-    println!("\tpaddingvalue: {}", plaintext[21]);
+pub fn cbc_decrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
+    check_block_aligned(input, cipher.block_size())?;
+
+    let mut output = Vec::from(input);
+    let previous_blocks = std::iter::once(iv).chain(input.chunks(cipher.block_size()));
+    for (block, previous) in output.chunks_mut(cipher.block_size()).zip(previous_blocks) {
+        cipher.decrypt_block(block);
+        xor_into(block, previous)?;
+    }
+    padding.unpad(&output, cipher.block_size())
+}
 
-    let mut full_ciphertext: Vec<u8> = vec![];
+// Like CBC, but each plaintext block is also XORed into the chaining value,
+// so a damaged ciphertext block garbles every block after it
+pub fn pcbc_encrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
+
+    let mut output = padding.pad(input, cipher.block_size())?;
+    let mut chain = Vec::from(iv);
+    for block in output.chunks_mut(cipher.block_size()) {
+        let plaintext = Vec::from(&*block);
+        xor_into(block, &chain)?;
+        cipher.encrypt_block(block);
+        chain.copy_from_slice(block);
+        xor_into(&mut chain, &plaintext)?;
+    }
+    Ok(output)
+}
 
-    let mut iv_vec: Vec<u8> = Vec::from(iv);
-    if iv_vec.len() != 16 {
-        return Err("simple_cbc_encrypt: IV must be 16B long!");
+pub fn pcbc_decrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
+    check_block_aligned(input, cipher.block_size())?;
+
+    let mut output = Vec::from(input);
+    let mut chain = Vec::from(iv);
+    for block in output.chunks_mut(cipher.block_size()) {
+        let ciphertext = Vec::from(&*block);
+        cipher.decrypt_block(block);
+        xor_into(block, &chain)?;
+        chain.copy_from_slice(block);
+        xor_into(&mut chain, &ciphertext)?;
     }
+    padding.unpad(&output, cipher.block_size())
+}
 
-    for i in 0..plaintext.len() / 16 {
-        let block_input = fixed_xor(&plaintext[i * 16..(i + 1) * 16], &iv_vec[0..16]).unwrap();
-        let block_ciphertext = openssl::symm::encrypt(
-            openssl::symm::Cipher::aes_128_ecb(),
-            key,
-            None,
-            &block_input,
-        )
-        .unwrap();
-        println!("Block ciphertext len? {}", block_ciphertext.len());
-        iv_vec.clone_from(&block_ciphertext);
-        full_ciphertext.extend(&block_ciphertext[0..16]);
+// How much of each cipher output CFB uses per step, and how far the shift
+// register moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CfbSegment {
+    Bit,
+    Byte,
+    Block,
+}
+
+// The stream modes (CFB, OFB, CTR) need no padding and return output the
+// same length as the input. Their IV or initial counter is one block, and
+// must never repeat under the same key.
+
+pub fn cfb_encrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    segment: CfbSegment,
+) -> Result<Vec<u8>, Error> {
+    cfb(cipher, input, iv, segment, true)
+}
+
+pub fn cfb_decrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    segment: CfbSegment,
+) -> Result<Vec<u8>, Error> {
+    cfb(cipher, input, iv, segment, false)
+}
+
+// Encrypts the shift register and XORs the leading output bits into the next
+// segment, then shifts the ciphertext segment into the register
+fn cfb(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    iv: &[u8],
+    segment: CfbSegment,
+    encrypt: bool,
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
+
+    let mut output = Vec::from(input);
+    let mut register = Vec::from(iv);
+    let mut keystream = vec![0u8; cipher.block_size()];
+    match segment {
+        CfbSegment::Bit => {
+            for byte in output.iter_mut() {
+                for bit in (0..8).rev() {
+                    keystream.copy_from_slice(&register);
+                    cipher.encrypt_block(&mut keystream);
+
+                    let input_bit = (*byte >> bit) & 1;
+                    let output_bit = input_bit ^ (keystream[0] >> 7);
+                    *byte ^= (input_bit ^ output_bit) << bit;
+                    let feedback = if encrypt { output_bit } else { input_bit };
+                    shift_in_bit(&mut register, feedback);
+                }
+            }
+        }
+        CfbSegment::Byte | CfbSegment::Block => {
+            let segment_len = match segment {
+                CfbSegment::Byte => 1,
+                _ => cipher.block_size(),
+            };
+            for chunk in output.chunks_mut(segment_len) {
+                keystream.copy_from_slice(&register);
+                cipher.encrypt_block(&mut keystream);
+
+                let input_chunk = Vec::from(&*chunk);
+                xor_into(chunk, &keystream[..chunk.len()])?;
+                let feedback = if encrypt { &*chunk } else { &input_chunk[..] };
+                register.rotate_left(feedback.len());
+                let start = register.len() - feedback.len();
+                register[start..].copy_from_slice(feedback);
+            }
+        }
     }
+    Ok(output)
+}
 
-    Ok(full_ciphertext)
+fn shift_in_bit(register: &mut [u8], bit: u8) {
+    let mut carry = bit;
+    for byte in register.iter_mut().rev() {
+        let next_carry = *byte >> 7;
+        *byte = (*byte << 1) | carry;
+        carry = next_carry;
+    }
 }
 
-pub fn simple_cbc_decrypt(input: &[u8], key: &[u8], iv: &[u8]) -> Result<Vec<u8>, &'static str> {
-    let ciphertext = Vec::from(input);
+// The keystream is the IV encrypted over and over
+pub fn ofb_encrypt(cipher: &dyn BlockCipher, input: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    check_iv_size(iv, cipher.block_size())?;
 
-    let mut full_plaintext: Vec<u8> = vec![];
+    let mut output = Vec::from(input);
+    let mut keystream = Vec::from(iv);
+    for chunk in output.chunks_mut(cipher.block_size()) {
+        cipher.encrypt_block(&mut keystream);
+        xor_into(chunk, &keystream[..chunk.len()])?;
+    }
+    Ok(output)
+}
 
-    let mut iv_vec: Vec<u8> = Vec::from(iv);
-    if iv_vec.len() != 16 {
-        return Err("simple_cbc_decrypt: IV must be 16B long!");
+pub fn ofb_decrypt(cipher: &dyn BlockCipher, input: &[u8], iv: &[u8]) -> Result<Vec<u8>, Error> {
+    ofb_encrypt(cipher, input, iv)
+}
+
+// The keystream is the encrypted counter block, which starts at
+// `initial_counter` and is incremented as one big-endian integer, wrapping
+// at the top. This matches openssl and NIST SP 800-38A. Protocols that split
+// the block into a nonce and a counter (Cryptopals challenge 18 uses a
+// little-endian counter) need to build their blocks themselves.
+pub fn ctr_encrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    initial_counter: &[u8],
+) -> Result<Vec<u8>, Error> {
+    check_iv_size(initial_counter, cipher.block_size())?;
+
+    let mut output = Vec::from(input);
+    let mut counter = Vec::from(initial_counter);
+    let mut keystream = vec![0u8; cipher.block_size()];
+    for chunk in output.chunks_mut(cipher.block_size()) {
+        keystream.copy_from_slice(&counter);
+        cipher.encrypt_block(&mut keystream);
+        xor_into(chunk, &keystream[..chunk.len()])?;
+        increment_counter(&mut counter);
     }
+    Ok(output)
+}
 
-    // For each block...
-    for i in 0..ciphertext.len() / 16 {
-        let block_input = &ciphertext[i * 16..(i + 1) * 16];
+pub fn ctr_decrypt(
+    cipher: &dyn BlockCipher,
+    input: &[u8],
+    initial_counter: &[u8],
+) -> Result<Vec<u8>, Error> {
+    ctr_encrypt(cipher, input, initial_counter)
+}
 
-        // Run the ciphertext through the core...
-        let mut crypter = openssl::symm::Crypter::new(
-            openssl::symm::Cipher::aes_128_ecb(),
-            openssl::symm::Mode::Decrypt,
-            key,
-            None,
-        )
-        .unwrap();
-        crypter.pad(false);
-        let mut block_output = vec![0u8; 32];
-        let _count = crypter
-            .update(block_input, block_output.as_mut_slice())
-            .unwrap();
-        // println!("\tCount: {}", count); // No need to print the count out most of the time.
+fn increment_counter(counter: &mut [u8]) {
+    for byte in counter.iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
 
-        // XOR the result with the current IV
-        let block_plaintext = fixed_xor(&block_output[0..16], &iv_vec[0..16]).unwrap();
-        full_plaintext.extend(&block_plaintext);
+fn check_iv_size(iv: &[u8], block_size: usize) -> Result<(), Error> {
+    if iv.len() != block_size {
+        return Err(Error::InvalidIvSize(iv.len()));
+    }
+    Ok(())
+}
 
-        // Update the IV with the previous ciphertext block
-        iv_vec = Vec::from(block_input);
+fn check_block_aligned(input: &[u8], block_size: usize) -> Result<(), Error> {
+    if !input.len().is_multiple_of(block_size) {
+        return Err(Error::NotBlockAligned {
+            len: input.len(),
+            block_size,
+        });
     }
+    Ok(())
+}
+
+// The simple_* functions are AES-128 with PKCS#7 unless a padding is given
+
+pub fn simple_ecb_encrypt(input: &[u8], key: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    simple_ecb_encrypt_with(input, key, &Pkcs7)
+}
+
+pub fn simple_ecb_encrypt_with(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    ecb_encrypt(&aes_128(key.as_ref())?, input, padding)
+}
+
+pub fn simple_ecb_decrypt(input: &[u8], key: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    simple_ecb_decrypt_with(input, key, &Pkcs7)
+}
+
+pub fn simple_ecb_decrypt_with(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    ecb_decrypt(&aes_128(key.as_ref())?, input, padding)
+}
+
+pub fn simple_cbc_encrypt(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    iv: &[u8],
+) -> Result<Vec<u8>, Error> {
+    simple_cbc_encrypt_with(input, key, iv, &Pkcs7)
+}
+
+pub fn simple_cbc_encrypt_with(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    cbc_encrypt(&aes_128(key.as_ref())?, input, iv, padding)
+}
+
+pub fn simple_cbc_decrypt(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    iv: &[u8],
+) -> Result<Vec<u8>, Error> {
+    simple_cbc_decrypt_with(input, key, iv, &Pkcs7)
+}
+
+pub fn simple_cbc_decrypt_with(
+    input: &[u8],
+    key: impl AsRef<[u8]>,
+    iv: &[u8],
+    padding: &dyn Padding,
+) -> Result<Vec<u8>, Error> {
+    cbc_decrypt(&aes_128(key.as_ref())?, input, iv, padding)
+}
 
-    Ok(full_plaintext)
+fn aes_128(key: &[u8]) -> Result<Aes, Error> {
+    if key.len() != 16 {
+        return Err(Error::InvalidKeySize(key.len()));
+    }
+    Aes::new(key)
 }
 
-pub fn encryption_oracle(input: &[u8]) -> Result<Vec<u8>, &'static str> {
-    let random_key = generate_random_aeskey(16).unwrap();
-    let random_iv = generate_random_aeskey(16).unwrap();
+pub fn encryption_oracle(input: &[u8]) -> Result<Vec<u8>, Error> {
+    let random_key = generate_random_aeskey(16)?;
+    let random_iv = generate_random_aeskey(16)?;
 
     let num_prepend: u8 = (rand::random::<u8>() % 6) + 5; // Uniform over 5-10
     let num_append: u8 = (rand::random::<u8>() % 6) + 5; // Uniform over 5-10
-    let prepend_bytes = generate_random_aeskey(num_prepend as usize).unwrap();
-    let append_bytes = generate_random_aeskey(num_append as usize).unwrap();
+    let prepend_bytes = generate_random_aeskey(num_prepend as usize)?;
+    let append_bytes = generate_random_aeskey(num_append as usize)?;
 
     let mut input_vecdeque = VecDeque::from(Vec::from(input));
-    for byte in prepend_bytes {
-        input_vecdeque.push_front(byte);
+    for byte in prepend_bytes.as_ref() {
+        input_vecdeque.push_front(*byte);
     }
-    for byte in append_bytes {
-        input_vecdeque.push_back(byte);
+    for byte in append_bytes.as_ref() {
+        input_vecdeque.push_back(*byte);
     }
     let input_vec = Vec::from(input_vecdeque);
 
-    let output_bytes: Vec<u8> = match rand::random::<u8>() % 2 {
-        0 => {
-            println!("Oracle chose cbc");
-            crate::block_ciphers::simple_cbc_encrypt(
-                input_vec.as_slice(),
-                random_key.as_slice(),
-                random_iv.as_slice(),
-            )
-            .unwrap()
-        }
-        1 => {
-            println!("Oracle chose ecb");
-            crate::block_ciphers::simple_ecb_encrypt(input_vec.as_slice(), random_key.as_slice())
-                .unwrap()
-        }
-        _ => return Err("encryption_oracle: Broke math! (% 2)"),
+    let output_bytes: Vec<u8> = if rand::random::<bool>() {
+        println!("Oracle chose cbc");
+        crate::block_ciphers::simple_cbc_encrypt(
+            input_vec.as_slice(),
+            &random_key,
+            random_iv.as_ref(),
+        )?
+    } else {
+        println!("Oracle chose ecb");
+        crate::block_ciphers::simple_ecb_encrypt(input_vec.as_slice(), &random_key)?
     };
 
     Ok(output_bytes)
 }
 
-pub fn stable_ecb_oracle(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+pub fn stable_ecb_oracle(input: &[u8]) -> Result<Vec<u8>, Error> {
     let random_key = Vec::from([
         48, 95, 77, 88, 214, 163, 80, 78, 205, 3, 202, 129, 233, 242, 221, 162,
     ]);
 
-    let unknown_string = base64_to_bytes(String::from("Um9sbGluJyBpbiBteSA1LjAKV2l0aCBteSByYWctdG9wIGRvd24gc28gbXkgaGFpciBjYW4gYmxvdwpUaGUgZ2lybGllcyBvbiBzdGFuZGJ5IHdhdmluZyBqdXN0IHRvIHNheSBoaQpEaWQgeW91IHN0b3A/IE5vLCBJIGp1c3QgZHJvdmUgYnkK")).unwrap();
+    let unknown_string = base64_to_bytes(String::from("Um9sbGluJyBpbiBteSA1LjAKV2l0aCBteSByYWctdG9wIGRvd24gc28gbXkgaGFpciBjYW4gYmxvdwpUaGUgZ2lybGllcyBvbiBzdGFuZGJ5IHdhdmluZyBqdXN0IHRvIHNheSBoaQpEaWQgeW91IHN0b3A/IE5vLCBJIGp1c3QgZHJvdmUgYnkK"))?;
     let mut input_vec = Vec::from(input);
     input_vec.extend_from_slice(&unknown_string);
 
     let output_bytes: Vec<u8> =
-        crate::block_ciphers::simple_ecb_encrypt(input_vec.as_slice(), random_key.as_slice())
-            .unwrap();
+        crate::block_ciphers::simple_ecb_encrypt(input_vec.as_slice(), random_key.as_slice())?;
 
     Ok(output_bytes)
 }
 
-pub fn detect_block_size(
-    encrypter: fn(&[u8]) -> Result<Vec<u8>, &'static str>,
-) -> Result<usize, &'static str> {
+pub fn detect_block_size(encrypter: fn(&[u8]) -> Result<Vec<u8>, Error>) -> Result<usize, Error> {
     let identified_block_size: usize;
     let mut inputstr = String::from("A");
 
-    let initial_result = encrypter(inputstr.as_bytes()).unwrap();
+    let initial_result = encrypter(inputstr.as_bytes())?;
     let init_block_size = initial_result.len();
     inputstr.push('A');
     loop {
-        let result = encrypter(inputstr.as_bytes()).unwrap();
+        let result = encrypter(inputstr.as_bytes())?;
         if result.len() != init_block_size {
             identified_block_size = result.len() - init_block_size;
             break;
@@ -174,40 +432,17 @@ pub enum CRYPTOTYPE {
 }
 
 fn find_repeated_blocks(ciphertext: &[u8], blocklen: usize) -> bool {
-    let num_blocks = ciphertext.len() / blocklen;
-    dbg!(num_blocks);
-
-    for i in 0..num_blocks {
-        for j in i + 1..num_blocks {
-            let l_idx = i * blocklen;
-            let r_idx = j * blocklen;
-            println!("Comparing blocks {} and {}", i, j);
-            print!("\tLeft:  ");
-            for byte in ciphertext[l_idx..l_idx + 16].iter() {
-                print!("{:02x} ", *byte);
-            }
-            print!("\tRight: ");
-            for byte in ciphertext[r_idx..r_idx + 16].iter() {
-                print!("{:02x} ", *byte);
-            }
-            if ciphertext[l_idx..l_idx + 16] == ciphertext[r_idx..r_idx + 16] {
-                println!("\tIdentical");
-                return true;
-            }
-            println!("\tNot identical");
-        }
-    }
+    let view = hexdump::BlockView::new(ciphertext, blocklen);
+    log::debug!("{}", view);
 
-    false
+    !view.duplicate_blocks().is_empty()
 }
 
-pub fn ecb_detector(
-    encrypter: fn(&[u8]) -> Result<Vec<u8>, &'static str>,
-) -> Result<CRYPTOTYPE, &'static str> {
+pub fn ecb_detector(encrypter: fn(&[u8]) -> Result<Vec<u8>, Error>) -> Result<CRYPTOTYPE, Error> {
     // Generate input long enough to have at least two full cipher blocks
     let blocklen: usize = 16;
     let chosen_plaintext = "X".repeat(4 * blocklen);
-    let oracle_ciphertext = encrypter(chosen_plaintext.as_bytes()).unwrap();
+    let oracle_ciphertext = encrypter(chosen_plaintext.as_bytes())?;
 
     if find_repeated_blocks(&oracle_ciphertext, blocklen) {
         Ok(CRYPTOTYPE::ECB)
@@ -219,7 +454,7 @@ pub fn ecb_detector(
 #[cfg(test)]
 mod tests {
     #[test]
-    pub fn simple_cbc_test() -> Result<(), &'static str> {
+    pub fn simple_cbc_test() -> Result<(), crate::Error> {
         use super::*;
         use crate::conversions;
         let key = b"YELLOW SUBMARINE";
@@ -242,23 +477,356 @@ mod tests {
             "Second recovered plaintext: {}",
             conversions::bytes_to_str(&second_recovered_plaintext).unwrap()
         );
+        assert_eq!(second_recovered_plaintext, input);
         Ok(())
     }
 
     #[test]
-    pub fn simple_keygen_test() -> Result<(), &'static str> {
+    pub fn simple_cbc_bad_iv_test() -> Result<(), crate::Error> {
         use super::*;
-        let rand_bytes = match generate_random_aeskey(16) {
-            Ok(r) => r,
-            Err(e) => return Err(e),
+        let key = b"YELLOW SUBMARINE";
+        let iv = &[0u8; 8];
+
+        let result = simple_cbc_encrypt(b"Hello from cbc land!", key, iv);
+        assert!(matches!(result, Err(Error::InvalidIvSize(8))));
+
+        let result = simple_cbc_decrypt(&[0u8; 20], key, &[0u8; 16]);
+        assert!(matches!(
+            result,
+            Err(Error::NotBlockAligned { len: 20, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn simple_cbc_padding_test() -> Result<(), crate::Error> {
+        use super::*;
+        use crate::PaddingError;
+        let key = b"YELLOW SUBMARINE";
+        let iv = [0u8; 16];
+
+        // "Hello from cbc land!" is 20 bytes, so the second block is "and!"
+        // and twelve 0x0c bytes. Flipping bits in the first ciphertext block
+        // flips the same bits of the second plaintext block.
+        let ciphertext = simple_cbc_encrypt(b"Hello from cbc land!", key, &iv)?;
+
+        let mut tampered = ciphertext.clone();
+        tampered[4] ^= 0x01;
+        assert!(matches!(
+            simple_cbc_decrypt(&tampered, key, &iv),
+            Err(Error::InvalidPadding(PaddingError::MismatchedByte {
+                position: 20
+            }))
+        ));
+
+        let mut tampered = ciphertext.clone();
+        tampered[15] ^= 0x0c;
+        assert!(matches!(
+            simple_cbc_decrypt(&tampered, key, &iv),
+            Err(Error::InvalidPadding(PaddingError::ZeroPadding))
+        ));
+
+        // A final 0x01 is valid padding on its own
+        let mut tampered = ciphertext.clone();
+        tampered[15] ^= 0x0c ^ 0x01;
+        let plaintext = simple_cbc_decrypt(&tampered, key, &iv)?;
+        assert_eq!(plaintext.len(), 31);
+        assert_eq!(&plaintext[16..20], b"and!");
+        Ok(())
+    }
+
+    #[test]
+    pub fn padding_schemes_test() -> Result<(), crate::Error> {
+        use super::*;
+        use crate::padding::{AnsiX923, Iso10126, Iso7816, NoPadding, ZeroPadding};
+        let key = b"YELLOW SUBMARINE";
+        let iv = [7u8; 16];
+        let input = b"Hello from legacy land!";
+
+        let schemes: [&dyn Padding; 5] = [&Pkcs7, &AnsiX923, &Iso10126, &Iso7816, &ZeroPadding];
+        for padding in schemes {
+            let ciphertext = simple_ecb_encrypt_with(input, key, padding)?;
+            assert_eq!(ciphertext.len(), 32);
+            assert_eq!(simple_ecb_decrypt_with(&ciphertext, key, padding)?, input);
+
+            let ciphertext = simple_cbc_encrypt_with(input, key, &iv, padding)?;
+            assert_eq!(
+                simple_cbc_decrypt_with(&ciphertext, key, &iv, padding)?,
+                input
+            );
+        }
+
+        // Default padding still matches openssl
+        let ciphertext = simple_ecb_encrypt(input, key)?;
+        let expected =
+            openssl::symm::encrypt(openssl::symm::Cipher::aes_128_ecb(), key, None, input).unwrap();
+        assert_eq!(ciphertext, expected);
+        assert_eq!(simple_ecb_decrypt(&ciphertext, key)?, input);
+
+        // With no padding, input must already be aligned
+        let aligned = &input[..16];
+        let ciphertext = simple_cbc_encrypt_with(aligned, key, &iv, &NoPadding)?;
+        assert_eq!(ciphertext.len(), 16);
+        let mut crypter = openssl::symm::Crypter::new(
+            openssl::symm::Cipher::aes_128_cbc(),
+            openssl::symm::Mode::Decrypt,
+            key,
+            Some(&iv),
+        )
+        .unwrap();
+        crypter.pad(false);
+        let mut recovered = vec![0u8; 32];
+        let count = crypter.update(&ciphertext, &mut recovered).unwrap();
+        assert_eq!(&recovered[..count], aligned);
+        assert!(matches!(
+            simple_ecb_encrypt_with(input, key, &NoPadding),
+            Err(Error::NotBlockAligned { len: 23, .. })
+        ));
+        Ok(())
+    }
+
+    // An 8 byte "cipher" that XORs with the key and rotates, and counts its
+    // calls. Enough to check the modes' chaining without AES in the way.
+    struct ToyCipher {
+        key: [u8; 8],
+        calls: std::cell::Cell<usize>,
+    }
+
+    impl super::BlockCipher for ToyCipher {
+        fn block_size(&self) -> usize {
+            8
+        }
+
+        fn encrypt_block(&self, block: &mut [u8]) {
+            self.calls.set(self.calls.get() + 1);
+            for (byte, key) in block.iter_mut().zip(self.key) {
+                *byte ^= key;
+            }
+            block.rotate_left(1);
+        }
+
+        fn decrypt_block(&self, block: &mut [u8]) {
+            self.calls.set(self.calls.get() + 1);
+            block.rotate_right(1);
+            for (byte, key) in block.iter_mut().zip(self.key) {
+                *byte ^= key;
+            }
+        }
+    }
+
+    #[test]
+    pub fn generic_modes_test() -> Result<(), crate::Error> {
+        use super::*;
+        let toy = ToyCipher {
+            key: *b"toy key!",
+            calls: std::cell::Cell::new(0),
         };
+        let input = b"Two blocks, nearly.";
+
+        let ciphertext = ecb_encrypt(&toy, input, &Pkcs7)?;
+        assert_eq!(ciphertext.len(), 24);
+        assert_eq!(toy.calls.get(), 3);
+        assert_eq!(ecb_decrypt(&toy, &ciphertext, &Pkcs7)?, input);
+
+        // Each CBC block is the cipher applied to the plaintext XOR the
+        // previous ciphertext block
+        let iv = [0x42u8; 8];
+        let ciphertext = cbc_encrypt(&toy, input, &iv, &Pkcs7)?;
+        let padded = Pkcs7.pad(input, 8)?;
+        let mut expected = [0u8; 8];
+        for (i, block) in padded.chunks(8).enumerate() {
+            let previous = if i == 0 {
+                &iv[..]
+            } else {
+                &ciphertext[(i - 1) * 8..i * 8]
+            };
+            expected.copy_from_slice(block);
+            xor_into(&mut expected, previous)?;
+            toy.encrypt_block(&mut expected);
+            assert_eq!(&ciphertext[i * 8..(i + 1) * 8], expected);
+        }
+        assert_eq!(cbc_decrypt(&toy, &ciphertext, &iv, &Pkcs7)?, input);
+
+        assert!(matches!(
+            cbc_encrypt(&toy, input, &[0u8; 16], &Pkcs7),
+            Err(Error::InvalidIvSize(16))
+        ));
+        assert!(matches!(
+            ecb_decrypt(&toy, &[0u8; 12], &Pkcs7),
+            Err(Error::NotBlockAligned {
+                len: 12,
+                block_size: 8
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn aes_key_sizes_test() -> Result<(), crate::Error> {
+        use super::*;
+        use openssl::symm::Cipher;
+        let input = b"The same mode code for every key size";
+        let iv = [9u8; 16];
+
+        let ciphers = [
+            (Cipher::aes_128_ecb(), Cipher::aes_128_cbc()),
+            (Cipher::aes_192_ecb(), Cipher::aes_192_cbc()),
+            (Cipher::aes_256_ecb(), Cipher::aes_256_cbc()),
+        ];
+        for (ecb, cbc) in ciphers {
+            let key = generate_random_aeskey(ecb.key_len())?;
+            let aes = Aes::new(&key)?;
+
+            let ciphertext = ecb_encrypt(&aes, input, &Pkcs7)?;
+            let expected = openssl::symm::encrypt(ecb, key.as_ref(), None, input).unwrap();
+            assert_eq!(ciphertext, expected);
+            assert_eq!(ecb_decrypt(&aes, &ciphertext, &Pkcs7)?, input);
+
+            let ciphertext = cbc_encrypt(&aes, input, &iv, &Pkcs7)?;
+            let expected = openssl::symm::encrypt(cbc, key.as_ref(), Some(&iv), input).unwrap();
+            assert_eq!(ciphertext, expected);
+            assert_eq!(cbc_decrypt(&aes, &ciphertext, &iv, &Pkcs7)?, input);
+        }
+
+        // The simple_* wrappers stay AES-128 only
+        assert!(matches!(
+            simple_cbc_encrypt(input, [0u8; 32], &iv),
+            Err(Error::InvalidKeySize(32))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn stream_modes_openssl_test() -> Result<(), crate::Error> {
+        use super::*;
+        use openssl::symm::Cipher;
+
+        // Lengths that end mid-block and mid-segment
+        let input: Vec<u8> = (0..77u8).collect();
+        let iv = generate_random_aeskey(16)?;
+        let iv = iv.as_ref();
+
+        type ModeFn = fn(&dyn BlockCipher, &[u8], &[u8]) -> Result<Vec<u8>, Error>;
+        let cfb1: [ModeFn; 2] = [
+            |c, i, v| cfb_encrypt(c, i, v, CfbSegment::Bit),
+            |c, i, v| cfb_decrypt(c, i, v, CfbSegment::Bit),
+        ];
+        let cfb8: [ModeFn; 2] = [
+            |c, i, v| cfb_encrypt(c, i, v, CfbSegment::Byte),
+            |c, i, v| cfb_decrypt(c, i, v, CfbSegment::Byte),
+        ];
+        let cfb128: [ModeFn; 2] = [
+            |c, i, v| cfb_encrypt(c, i, v, CfbSegment::Block),
+            |c, i, v| cfb_decrypt(c, i, v, CfbSegment::Block),
+        ];
+        let ofb: [ModeFn; 2] = [ofb_encrypt, ofb_decrypt];
+        let ctr: [ModeFn; 2] = [ctr_encrypt, ctr_decrypt];
+
+        let modes = [
+            ("cfb1", cfb1, Cipher::aes_128_cfb1(), Cipher::aes_256_cfb1()),
+            ("cfb8", cfb8, Cipher::aes_128_cfb8(), Cipher::aes_256_cfb8()),
+            (
+                "cfb128",
+                cfb128,
+                Cipher::aes_128_cfb128(),
+                Cipher::aes_256_cfb128(),
+            ),
+            ("ofb", ofb, Cipher::aes_128_ofb(), Cipher::aes_256_ofb()),
+            ("ctr", ctr, Cipher::aes_128_ctr(), Cipher::aes_256_ctr()),
+        ];
+        for (name, [encrypt, decrypt], aes_128, aes_256) in modes {
+            for openssl_cipher in [aes_128, aes_256] {
+                let key = generate_random_aeskey(openssl_cipher.key_len())?;
+                let aes = Aes::new(&key)?;
+
+                let ciphertext = encrypt(&aes, &input, iv)?;
+                let expected =
+                    openssl::symm::encrypt(openssl_cipher, key.as_ref(), Some(iv), &input).unwrap();
+                assert_eq!(ciphertext, expected, "{}", name);
+                assert_eq!(decrypt(&aes, &ciphertext, iv)?, input, "{}", name);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn ctr_counter_test() -> Result<(), crate::Error> {
+        use super::*;
+        use openssl::symm::Cipher;
+
+        // The counter carries across the whole block, so openssl and we agree
+        // when the low bytes wrap into the nonce
+        let key = [3u8; 16];
+        let mut counter = [0xffu8; 16];
+        counter[..7].copy_from_slice(b"nonce!!");
+        let input = [0u8; 48];
+        let ciphertext = ctr_encrypt(&Aes::new(key)?, &input, &counter)?;
+        let expected =
+            openssl::symm::encrypt(Cipher::aes_128_ctr(), &key, Some(&counter), &input).unwrap();
+        assert_eq!(ciphertext, expected);
+
+        let mut counter = [0xffu8; 4];
+        increment_counter(&mut counter);
+        assert_eq!(counter, [0u8; 4]);
+
+        assert!(matches!(
+            ctr_encrypt(&Aes::new(key)?, &input, &[0u8; 8]),
+            Err(Error::InvalidIvSize(8))
+        ));
+        assert!(matches!(
+            cfb_encrypt(&Aes::new(key)?, &input, &[0u8; 15], CfbSegment::Bit),
+            Err(Error::InvalidIvSize(15))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn pcbc_test() -> Result<(), crate::Error> {
+        use super::*;
+        let aes = Aes::new(b"YELLOW SUBMARINE")?;
+        let iv = [5u8; 16];
+        let input = b"PCBC spreads damage to every block after the one it hits";
+
+        let ciphertext = pcbc_encrypt(&aes, input, &iv, &Pkcs7)?;
+        assert_eq!(pcbc_decrypt(&aes, &ciphertext, &iv, &Pkcs7)?, input);
+
+        // The first block is plain CBC
+        let cbc = cbc_encrypt(&aes, input, &iv, &Pkcs7)?;
+        assert_eq!(ciphertext[..16], cbc[..16]);
+        assert_ne!(ciphertext[16..32], cbc[16..32]);
+
+        // Damage in the second block garbles the rest, where CBC would only
+        // hurt the second and third
+        let mut tampered = ciphertext.clone();
+        tampered[20] ^= 1;
+        let plaintext = pcbc_decrypt(&aes, &tampered, &iv, &crate::padding::NoPadding)?;
+        assert_eq!(plaintext[..16], input[..16]);
+        for block in 1..4 {
+            let range = block * 16..(block + 1) * 16;
+            assert_ne!(plaintext[range.clone()], Pkcs7.pad(input, 16)?[range]);
+        }
+
+        // Swapping two adjacent ciphertext blocks leaves the blocks after
+        // them intact, PCBC's well known weakness
+        let mut swapped = ciphertext.clone();
+        let (first, second) = swapped.split_at_mut(32);
+        first[16..32].swap_with_slice(&mut second[..16]);
+        let plaintext = pcbc_decrypt(&aes, &swapped, &iv, &Pkcs7)?;
+        assert_eq!(plaintext[48..], input[48..]);
+        Ok(())
+    }
+
+    #[test]
+    pub fn simple_keygen_test() -> Result<(), crate::Error> {
+        use super::*;
+        let rand_bytes = generate_random_aeskey(16)?;
         dbg!(&rand_bytes);
         assert_eq!(rand_bytes.len(), 16usize);
         Ok(())
     }
 
     #[test]
-    pub fn encryption_oracle_test() -> Result<(), &'static str> {
+    pub fn encryption_oracle_test() -> Result<(), crate::Error> {
         use super::*;
 
         let input = b"Hello from cbc land!";
@@ -268,7 +836,7 @@ mod tests {
     }
 
     #[test]
-    pub fn stable_oracle_test() -> Result<(), &'static str> {
+    pub fn stable_oracle_test() -> Result<(), crate::Error> {
         use super::*;
 
         let input = b"Hello from ecb land!";
@@ -278,7 +846,7 @@ mod tests {
     }
 
     #[test]
-    pub fn block_size_ident_test() -> Result<(), &'static str> {
+    pub fn block_size_ident_test() -> Result<(), crate::Error> {
         use super::*;
 
         let block_size = detect_block_size(stable_ecb_oracle).unwrap();
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::scoring::Scorer;
+use crate::xor::{self, KeyLenCandidate, KeyLenEstimator};
+use crate::Error;
+
+// All ciphers here work on the 26 letter Latin alphabet. Letters keep their
+// case, and every other byte passes through unchanged.
+const ALPHABET_LEN: u8 = 26;
+
+// Multipliers with an inverse mod 26
+const AFFINE_MULTIPLIERS: [u8; 12] = [1, 3, 5, 7, 9, 11, 15, 17, 19, 21, 23, 25];
+
+// Letters from most to least common in English, used to seed substitution
+// solving from the ciphertext's own frequencies
+const ENGLISH_BY_FREQUENCY: &[u8; 26] = b"ETAOINSHRDLCUMWFGYPBVKJXQZ";
+
+// How many of the best key lengths from each estimator break_vigenere solves
+const VIGENERE_LEN_ATTEMPTS: usize = 3;
+
+pub fn caesar_encrypt(input: &[u8], shift: u8) -> Vec<u8> {
+    map_letters(input, |x| (x + shift % ALPHABET_LEN) % ALPHABET_LEN)
+}
+
+pub fn caesar_decrypt(input: &[u8], shift: u8) -> Vec<u8> {
+    caesar_encrypt(input, ALPHABET_LEN - shift % ALPHABET_LEN)
+}
+
+// x -> a * x + b (mod 26). `a` must be coprime with 26.
+pub fn affine_encrypt(input: &[u8], a: u8, b: u8) -> Result<Vec<u8>, Error> {
+    affine_inverse(a)?;
+    Ok(map_letters(input, |x| {
+        ((a as u16 * x as u16 + b as u16) % ALPHABET_LEN as u16) as u8
+    }))
+}
+
+pub fn affine_decrypt(input: &[u8], a: u8, b: u8) -> Result<Vec<u8>, Error> {
+    let inverse = affine_inverse(a)?;
+    Ok(map_letters(input, |y| {
+        let shifted = (y + ALPHABET_LEN - b % ALPHABET_LEN) as u16;
+        ((inverse as u16 * shifted) % ALPHABET_LEN as u16) as u8
+    }))
+}
+
+// The key is a word of letters, in either case. It only advances on letters,
+// so spacing and punctuation don't shift it.
+pub fn vigenere_encrypt(input: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let shifts = vigenere_shifts(key)?;
+    let mut i = 0;
+    Ok(map_letters(input, |x| {
+        let shift = shifts[i % shifts.len()];
+        i += 1;
+        (x + shift) % ALPHABET_LEN
+    }))
+}
+
+pub fn vigenere_decrypt(input: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let shifts = vigenere_shifts(key)?;
+    let mut i = 0;
+    Ok(map_letters(input, |y| {
+        let shift = shifts[i % shifts.len()];
+        i += 1;
+        (y + ALPHABET_LEN - shift) % ALPHABET_LEN
+    }))
+}
+
+// `key` is the cipher alphabet: plaintext A becomes key[0], B key[1], ...
+pub fn substitution_encrypt(input: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let table = substitution_table(key)?;
+    Ok(map_letters(input, |x| table[x as usize]))
+}
+
+pub fn substitution_decrypt(input: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let table = substitution_table(key)?;
+    let mut inverse = [0u8; 26];
+    for (plain, cipher) in table.iter().enumerate() {
+        inverse[*cipher as usize] = plain as u8;
+    }
+    Ok(map_letters(input, |y| inverse[y as usize]))
+}
+
+#[derive(Clone, Debug)]
+pub struct CaesarCandidate {
+    pub shift: u8,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Every shift, best first
+pub fn rank_caesar_shifts(ciphertext: &[u8], scorer: &dyn Scorer) -> Vec<CaesarCandidate> {
+    let mut candidates: Vec<CaesarCandidate> = (0..ALPHABET_LEN)
+        .map(|shift| {
+            let plaintext = caesar_decrypt(ciphertext, shift);
+            let score = scorer.score(&plaintext);
+            CaesarCandidate {
+                shift,
+                plaintext,
+                score,
+            }
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+    candidates
+}
+
+pub fn break_caesar(ciphertext: &[u8], scorer: &dyn Scorer) -> CaesarCandidate {
+    rank_caesar_shifts(ciphertext, scorer).remove(0)
+}
+
+#[derive(Clone, Debug)]
+pub struct AffineCandidate {
+    pub a: u8,
+    pub b: u8,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Tries all 312 keys
+pub fn break_affine(ciphertext: &[u8], scorer: &dyn Scorer) -> Result<AffineCandidate, Error> {
+    let mut best: Option<AffineCandidate> = None;
+    for a in AFFINE_MULTIPLIERS {
+        for b in 0..ALPHABET_LEN {
+            let plaintext = affine_decrypt(ciphertext, a, b)?;
+            let score = scorer.score(&plaintext);
+            if best.as_ref().is_none_or(|best| score < best.score) {
+                best = Some(AffineCandidate {
+                    a,
+                    b,
+                    plaintext,
+                    score,
+                });
+            }
+        }
+    }
+
+    Ok(best.expect("at least one affine key is tried"))
+}
+
+// Kasiski examination: distances between repeats of the same trigram tend to
+// be multiples of the key length. `score` is how many of those distances
+// each length divides, relative to how many it would divide by chance, so
+// small lengths aren't favoured just for dividing more numbers. Only letters
+// are considered. Best first.
+pub fn kasiski_key_lens(
+    ciphertext: &[u8],
+    min_len: usize,
+    max_len: usize,
+) -> Result<Vec<KeyLenCandidate>, Error> {
+    if min_len == 0 || min_len >= max_len {
+        return Err(Error::InvalidKeyLenRange {
+            start: min_len,
+            end: max_len,
+        });
+    }
+
+    let letters = letters_only(ciphertext);
+    let mut positions: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (i, trigram) in letters.windows(3).enumerate() {
+        positions.entry(trigram).or_default().push(i);
+    }
+
+    let distances: Vec<usize> = positions
+        .values()
+        .flat_map(|p| p.windows(2).map(|w| w[1] - w[0]))
+        .collect();
+
+    let mut result_vec: Vec<KeyLenCandidate> = (min_len..max_len)
+        .map(|length| {
+            let divided = distances
+                .iter()
+                .filter(|d| d.is_multiple_of(length))
+                .count();
+            let expected = distances.len() as f32 / length as f32;
+            KeyLenCandidate {
+                length,
+                score: if expected > 0.0 {
+                    divided as f32 / expected
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect();
+
+    result_vec.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(result_vec)
+}
+
+#[derive(Clone, Debug)]
+pub struct VigenereBreak {
+    pub key: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Key lengths are proposed by both the index of coincidence and Kasiski
+// examination; each candidate is solved column by column as a Caesar cipher
+// and the best scoring plaintext wins. Both estimators score multiples of the
+// true length as well as the length itself, so divisors of every proposed
+// length are tried too. A longer key has more freedom to fit the scorer, so
+// on short texts a unigram scorer can prefer a multiple of the true key with
+// one column wrong; n-gram scorers don't.
+pub fn break_vigenere(
+    ciphertext: &[u8],
+    key_len_range: Range<usize>,
+    scorer: &dyn Scorer,
+) -> Result<VigenereBreak, Error> {
+    let letters = letters_only(ciphertext);
+    let by_ioc = xor::rank_key_lens_with(
+        &letters,
+        key_len_range.start,
+        key_len_range.end,
+        KeyLenEstimator::IndexOfCoincidence,
+    )?;
+    let by_kasiski = kasiski_key_lens(ciphertext, key_len_range.start, key_len_range.end)?;
+
+    let mut key_lens: Vec<usize> = vec![];
+    for candidate in by_ioc
+        .iter()
+        .take(VIGENERE_LEN_ATTEMPTS)
+        .chain(by_kasiski.iter().take(VIGENERE_LEN_ATTEMPTS))
+    {
+        let divisors = (key_len_range.start..=candidate.length)
+            .filter(|len| candidate.length.is_multiple_of(*len));
+        for len in divisors {
+            if !key_lens.contains(&len) {
+                key_lens.push(len);
+            }
+        }
+    }
+
+    let mut best: Option<VigenereBreak> = None;
+    for key_len in key_lens {
+        let mut key: Vec<u8> = xor::transpose(&letters, key_len)
+            .iter()
+            .map(|column| b'A' + break_caesar(column, scorer).shift)
+            .collect();
+
+        // As with repeating-key XOR, a multiple of the key length recovers
+        // the key repeated
+        key.truncate(xor::shortest_period(&key));
+        let plaintext = vigenere_decrypt(ciphertext, &key)?;
+        let score = scorer.score(&plaintext);
+
+        if best.as_ref().is_none_or(|b| score < b.score) {
+            best = Some(VigenereBreak {
+                key,
+                plaintext,
+                score,
+            });
+        }
+    }
+
+    best.ok_or(Error::InvalidKeyLenRange {
+        start: key_len_range.start,
+        end: key_len_range.end,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubstitutionOptions {
+    // Independent climbs; the first starts from a frequency-matched key, the
+    // rest from random ones
+    pub restarts: usize,
+    // Seeds the random starting keys
+    pub seed: u64,
+}
+
+impl Default for SubstitutionOptions {
+    fn default() -> Self {
+        SubstitutionOptions {
+            restarts: 4,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SubstitutionBreak {
+    // Cipher alphabet, in the form substitution_decrypt takes
+    pub key: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Hill climbing over cipher alphabets: try swapping every pair of letters in
+// the key, keeping each swap that improves the plaintext's score, until a full
+// pass makes no improvement. Needs a scorer that looks at more
+// than single letter frequencies, such as scoring::NgramLikelihood, since
+// those are identical for every key that only permutes rare letters.
+pub fn break_substitution(
+    ciphertext: &[u8],
+    scorer: &dyn Scorer,
+    options: SubstitutionOptions,
+) -> Result<SubstitutionBreak, Error> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let mut best: Option<SubstitutionBreak> = None;
+    for restart in 0..options.restarts.max(1) {
+        let mut key = if restart == 0 {
+            frequency_matched_key(ciphertext)
+        } else {
+            let mut key: Vec<u8> = (b'A'..=b'Z').collect();
+            key.shuffle(&mut rng);
+            key
+        };
+        let mut score = scorer.score(&substitution_decrypt(ciphertext, &key)?);
+
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for i in 0..key.len() {
+                for j in i + 1..key.len() {
+                    key.swap(i, j);
+                    let candidate = scorer.score(&substitution_decrypt(ciphertext, &key)?);
+                    if candidate < score {
+                        score = candidate;
+                        improved = true;
+                    } else {
+                        key.swap(i, j);
+                    }
+                }
+            }
+        }
+
+        if best.as_ref().is_none_or(|b| score < b.score) {
+            let plaintext = substitution_decrypt(ciphertext, &key)?;
+            best = Some(SubstitutionBreak {
+                key,
+                plaintext,
+                score,
+            });
+        }
+    }
+
+    Ok(best.expect("at least one climb is run"))
+}
+
+// Maps the nth most common ciphertext letter to the nth most common English one
+fn frequency_matched_key(ciphertext: &[u8]) -> Vec<u8> {
+    let mut counts = [0usize; 26];
+    for letter in letters_only(ciphertext) {
+        counts[(letter - b'A') as usize] += 1;
+    }
+
+    let mut by_frequency: Vec<u8> = (0..ALPHABET_LEN).collect();
+    by_frequency.sort_by(|a, b| counts[*b as usize].cmp(&counts[*a as usize]));
+
+    let mut key = vec![0u8; 26];
+    for (plain, cipher) in ENGLISH_BY_FREQUENCY.iter().zip(by_frequency) {
+        key[(plain - b'A') as usize] = b'A' + cipher;
+    }
+    key
+}
+
+// Applies `f` to the alphabet index of every letter, keeping its case
+fn map_letters<F: FnMut(u8) -> u8>(input: &[u8], mut f: F) -> Vec<u8> {
+    input
+        .iter()
+        .map(|c| match c {
+            b'a'..=b'z' => b'a' + f(c - b'a'),
+            b'A'..=b'Z' => b'A' + f(c - b'A'),
+            _ => *c,
+        })
+        .collect()
+}
+
+fn letters_only(input: &[u8]) -> Vec<u8> {
+    input
+        .iter()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn affine_inverse(a: u8) -> Result<u8, Error> {
+    (1..ALPHABET_LEN)
+        .find(|x| (a as u16 * *x as u16) % ALPHABET_LEN as u16 == 1)
+        .ok_or(Error::InvalidKey(
+            "affine multiplier must be coprime with 26",
+        ))
+}
+
+fn vigenere_shifts(key: &[u8]) -> Result<Vec<u8>, Error> {
+    if key.is_empty() {
+        return Err(Error::InvalidKeySize(0));
+    }
+    if !key.iter().all(|c| c.is_ascii_alphabetic()) {
+        return Err(Error::InvalidKey("vigenere keys must be letters"));
+    }
+    Ok(key.iter().map(|c| c.to_ascii_uppercase() - b'A').collect())
+}
+
+fn substitution_table(key: &[u8]) -> Result<[u8; 26], Error> {
+    if key.len() != ALPHABET_LEN as usize {
+        return Err(Error::InvalidKeySize(key.len()));
+    }
+
+    let mut table = [0u8; 26];
+    let mut seen = [false; 26];
+    for (entry, c) in table.iter_mut().zip(key) {
+        if !c.is_ascii_alphabetic() {
+            return Err(Error::InvalidKey("substitution keys must be letters"));
+        }
+        let index = c.to_ascii_uppercase() - b'A';
+        if seen[index as usize] {
+            return Err(Error::InvalidKey("substitution key repeats a letter"));
+        }
+        seen[index as usize] = true;
+        *entry = index;
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::{ChiSquared, FrequencyDistance, NgramLikelihood};
+
+    // Not part of the sample scoring::NgramLikelihood::english is trained on
+    const PLAINTEXT: &[u8] = b"The old lighthouse keeper climbed the spiral stairs every evening just before sunset, carrying a lantern in one hand and a flask of strong black coffee in the other. From the gallery at the top he could see the fishing boats returning to the harbour, the gulls wheeling above the breakwater, and far out on the horizon the faint grey line where the weather was coming in from the west.";
+
+    #[test]
+    fn test_classical_round_trips() -> Result<(), Error> {
+        let input = b"Attack at dawn! Zebras, 42 of them.";
+
+        assert_eq!(caesar_encrypt(b"Hello, World", 3), b"Khoor, Zruog");
+        assert_eq!(caesar_decrypt(&caesar_encrypt(input, 29), 29), input);
+
+        assert_eq!(affine_encrypt(b"affine cipher", 5, 8)?, b"ihhwvc swfrcp");
+        assert_eq!(affine_decrypt(&affine_encrypt(input, 7, 3)?, 7, 3)?, input);
+        assert!(matches!(
+            affine_encrypt(input, 13, 1),
+            Err(Error::InvalidKey(_))
+        ));
+
+        assert_eq!(
+            vigenere_encrypt(b"ATTACK AT DAWN", b"lemon")?,
+            b"LXFOPV EF RNHR"
+        );
+        assert_eq!(
+            vigenere_decrypt(&vigenere_encrypt(input, b"Key")?, b"kEY")?,
+            input
+        );
+        assert!(matches!(
+            vigenere_encrypt(input, b""),
+            Err(Error::InvalidKeySize(0))
+        ));
+
+        let key = b"QWERTYUIOPASDFGHJKLZXCVBNM";
+        assert_eq!(substitution_encrypt(b"abc XYZ", key)?, b"qwe BNM");
+        assert_eq!(
+            substitution_decrypt(&substitution_encrypt(input, key)?, key)?,
+            input
+        );
+        assert!(matches!(
+            substitution_encrypt(input, b"QWERTYUIOPASDFGHJKLZXCVBNQ"),
+            Err(Error::InvalidKey(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_break_caesar_and_affine() -> Result<(), Error> {
+        let ciphertext = caesar_encrypt(PLAINTEXT, 11);
+        let result = break_caesar(&ciphertext, &FrequencyDistance);
+        assert_eq!(result.shift, 11);
+        assert_eq!(result.plaintext, PLAINTEXT);
+
+        let ciphertext = affine_encrypt(PLAINTEXT, 17, 20)?;
+        let result = break_affine(&ciphertext, &ChiSquared)?;
+        assert_eq!((result.a, result.b), (17, 20));
+        assert_eq!(result.plaintext, PLAINTEXT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_break_vigenere() -> Result<(), Error> {
+        let ciphertext = vigenere_encrypt(PLAINTEXT, b"AUSTEN")?;
+
+        let kasiski = kasiski_key_lens(&ciphertext, 2, 16)?;
+        assert!(kasiski.iter().take(3).any(|c| c.length % 6 == 0));
+
+        let result = break_vigenere(&ciphertext, 2..16, &NgramLikelihood::english())?;
+        assert_eq!(result.key, b"AUSTEN");
+        assert_eq!(result.plaintext, PLAINTEXT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_break_substitution() -> Result<(), Error> {
+        let key = b"QWERTYUIOPASDFGHJKLZXCVBNM";
+        let ciphertext = substitution_encrypt(PLAINTEXT, key)?;
+
+        let scorer = NgramLikelihood::english();
+        let result = break_substitution(&ciphertext, &scorer, SubstitutionOptions::default())?;
+        assert_eq!(result.plaintext, PLAINTEXT);
+
+        // Letters absent from the plaintext can't be pinned down, but every
+        // one that appears must be right
+        for c in PLAINTEXT.iter().filter(|c| c.is_ascii_lowercase()) {
+            let i = (c - b'a') as usize;
+            assert_eq!(result.key[i], key[i]);
+        }
+        Ok(())
+    }
+}
@@ -1,31 +1,352 @@
-use base64::{engine::general_purpose, Engine as _};
-use std::num::ParseIntError;
+use std::io::{self, Read};
 
-pub fn read_hexstr_as_bytes(inputstr: &str) -> Result<Vec<u8>, ParseIntError> {
-    (0..inputstr.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&inputstr[i..i + 2], 16))
-        .collect()
+use base64::engine::general_purpose::{self, GeneralPurpose};
+use base64::Engine as _;
+
+use crate::hex;
+use crate::Error;
+
+const MIME_LINE_LEN: usize = 76;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base64Variant {
+    Standard,
+    StandardNoPad,
+    UrlSafe,
+    UrlSafeNoPad,
+    // Standard alphabet wrapped at 76 columns; whitespace is ignored on decode
+    Mime,
+}
+
+impl Base64Variant {
+    fn encoder(self) -> &'static GeneralPurpose {
+        match self {
+            Base64Variant::Standard | Base64Variant::Mime => &general_purpose::STANDARD,
+            Base64Variant::StandardNoPad => &general_purpose::STANDARD_NO_PAD,
+            Base64Variant::UrlSafe => &general_purpose::URL_SAFE,
+            Base64Variant::UrlSafeNoPad => &general_purpose::URL_SAFE_NO_PAD,
+        }
+    }
+
+    // Unpadded variants also accept padded input, since tokens in the wild
+    // are frequently re-padded by whatever produced them.
+    fn decoder(self) -> &'static GeneralPurpose {
+        match self {
+            Base64Variant::Standard | Base64Variant::Mime => &general_purpose::STANDARD,
+            Base64Variant::StandardNoPad => &general_purpose::STANDARD_NO_PAD_INDIFFERENT,
+            Base64Variant::UrlSafe => &general_purpose::URL_SAFE,
+            Base64Variant::UrlSafeNoPad => &general_purpose::URL_SAFE_NO_PAD_INDIFFERENT,
+        }
+    }
+}
+
+pub fn read_hexstr_as_bytes(inputstr: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(inputstr)
 }
 
-pub fn bytes_to_base64(bytes: Vec<u8>) -> Result<String, &'static str> {
+pub fn bytes_to_hex(bytes: &[u8]) -> Result<String, Error> {
+    Ok(hex::encode(bytes))
+}
+
+pub fn bytes_to_base64(bytes: Vec<u8>) -> Result<String, Error> {
     let b64 = general_purpose::STANDARD.encode(bytes);
     Ok(b64)
 }
 
-pub fn base64_to_bytes(input: String) -> Result<Vec<u8>, &'static str> {
-    match general_purpose::STANDARD.decode(input) {
-        Ok(res) => Ok(res),
-        Err(_) => Err("Could not decode!"),
+pub fn base64_to_bytes<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, Error> {
+    Ok(general_purpose::STANDARD.decode(input)?)
+}
+
+pub fn bytes_to_base64_with(bytes: &[u8], variant: Base64Variant) -> Result<String, Error> {
+    let b64 = variant.encoder().encode(bytes);
+    if variant != Base64Variant::Mime {
+        return Ok(b64);
+    }
+
+    // Base64 output is pure ASCII, so any byte index is a char boundary
+    let lines: Vec<&str> = (0..b64.len())
+        .step_by(MIME_LINE_LEN)
+        .map(|i| &b64[i..(i + MIME_LINE_LEN).min(b64.len())])
+        .collect();
+    Ok(lines.join("\r\n"))
+}
+
+pub fn base64_to_bytes_with<T: AsRef<[u8]>>(
+    input: T,
+    variant: Base64Variant,
+) -> Result<Vec<u8>, Error> {
+    let input = input.as_ref();
+    if variant != Base64Variant::Mime {
+        return Ok(variant.decoder().decode(input)?);
     }
+
+    let stripped: Vec<u8> = input
+        .iter()
+        .copied()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    Ok(variant.decoder().decode(stripped)?)
 }
 
-pub fn bytes_to_str(bytes: &[u8]) -> Result<String, &'static str> {
+pub fn base64_stream_to_bytes<R: Read>(
+    reader: R,
+    variant: Base64Variant,
+) -> Result<Vec<u8>, Error> {
+    let mut output: Vec<u8> = vec![];
+    Base64Reader::new(reader, variant).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+pub fn bytes_to_str(bytes: &[u8]) -> Result<String, Error> {
+    bytes_to_utf8(bytes)
+}
+
+pub fn str_to_bytes(input: &str) -> Result<Vec<u8>, Error> {
+    Ok(Vec::from(input.as_bytes()))
+}
+
+pub fn bytes_to_utf8(bytes: &[u8]) -> Result<String, Error> {
+    Ok(String::from(std::str::from_utf8(bytes)?))
+}
+
+pub fn bytes_to_utf8_lossy(bytes: &[u8]) -> Result<String, Error> {
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+pub fn bytes_to_latin1(bytes: &[u8]) -> Result<String, Error> {
     let char_str: String = bytes.iter().map(|c| *c as char).collect();
     Ok(char_str)
 }
 
-pub fn str_to_bytes(input: &str) -> Result<Vec<u8>, &'static str> {
-    let bytevec: Vec<u8> = input.chars().map(|c| c as u8).collect();
-    Ok(bytevec)
+pub fn latin1_to_bytes(input: &str) -> Result<Vec<u8>, Error> {
+    input
+        .char_indices()
+        .map(|(position, c)| u8::try_from(c).map_err(|_| Error::NotLatin1 { position, found: c }))
+        .collect()
+}
+
+// Printable ASCII is kept as-is, everything else becomes \n, \r, \t, \\ or
+// \xNN, so the output is always a single line that escaped_to_bytes reverses.
+pub fn bytes_to_escaped(bytes: &[u8]) -> Result<String, Error> {
+    let mut output = String::with_capacity(bytes.len());
+    for byte in bytes {
+        match *byte {
+            b'\\' => output.push_str("\\\\"),
+            b'\n' => output.push_str("\\n"),
+            b'\r' => output.push_str("\\r"),
+            b'\t' => output.push_str("\\t"),
+            0x20..=0x7e => output.push(*byte as char),
+            _ => output.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    Ok(output)
+}
+
+pub fn escaped_to_bytes(input: &str) -> Result<Vec<u8>, Error> {
+    let bytes = input.as_bytes();
+    let mut output: Vec<u8> = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            output.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let invalid = Error::InvalidEscape { position: i };
+        match bytes.get(i + 1) {
+            Some(b'\\') => output.push(b'\\'),
+            Some(b'n') => output.push(b'\n'),
+            Some(b'r') => output.push(b'\r'),
+            Some(b't') => output.push(b'\t'),
+            Some(b'x') => {
+                // from_str_radix alone would also accept a leading '+'
+                let digits = input
+                    .get(i + 2..i + 4)
+                    .filter(|d| d.bytes().all(|c| c.is_ascii_hexdigit()))
+                    .ok_or(invalid)?;
+                output.push(u8::from_str_radix(digits, 16).unwrap_or_default());
+                i += 2;
+            }
+            _ => return Err(invalid),
+        }
+        i += 2;
+    }
+
+    Ok(output)
+}
+
+pub struct Base64Reader<R: Read> {
+    inner: base64::read::DecoderReader<'static, GeneralPurpose, SkipWhitespace<R>>,
+}
+
+impl<R: Read> Base64Reader<R> {
+    pub fn new(inner: R, variant: Base64Variant) -> Self {
+        let source = SkipWhitespace {
+            inner,
+            enabled: variant == Base64Variant::Mime,
+        };
+        Base64Reader {
+            inner: base64::read::DecoderReader::new(source, variant.decoder()),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().inner
+    }
+}
+
+impl<R: Read> Read for Base64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+struct SkipWhitespace<R: Read> {
+    inner: R,
+    enabled: bool,
+}
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let count = self.inner.read(buf)?;
+            if count == 0 || !self.enabled {
+                return Ok(count);
+            }
+
+            let mut kept = 0;
+            for i in 0..count {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+
+            // A chunk of pure whitespace is not end of stream, keep reading
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf8_conversions() -> Result<(), Error> {
+        let text = "naïve café ☕";
+        let bytes = str_to_bytes(text)?;
+        assert_eq!(bytes.len(), text.len());
+        assert_eq!(bytes_to_str(&bytes)?, text);
+
+        assert!(matches!(
+            bytes_to_utf8(b"ab\xffcd"),
+            Err(Error::InvalidUtf8(_))
+        ));
+        assert_eq!(bytes_to_utf8_lossy(b"ab\xffcd")?, "ab\u{fffd}cd");
+        Ok(())
+    }
+
+    #[test]
+    fn test_latin1_conversions() -> Result<(), Error> {
+        let bytes: Vec<u8> = (0..=255u8).collect();
+        let text = bytes_to_latin1(&bytes)?;
+        assert_eq!(text.chars().count(), 256);
+        assert_eq!(latin1_to_bytes(&text)?, bytes);
+
+        assert!(matches!(
+            latin1_to_bytes("ok ☕"),
+            Err(Error::NotLatin1 {
+                position: 3,
+                found: '☕'
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escaped_conversions() -> Result<(), Error> {
+        let bytes = b"Hi\\there\n\x00\x7f\xff\t";
+        let escaped = bytes_to_escaped(bytes)?;
+        assert_eq!(escaped, "Hi\\\\there\\n\\x00\\x7f\\xff\\t");
+        assert_eq!(escaped_to_bytes(&escaped)?, bytes);
+
+        let all: Vec<u8> = (0..=255u8).collect();
+        assert_eq!(escaped_to_bytes(&bytes_to_escaped(&all)?)?, all);
+
+        assert!(matches!(
+            escaped_to_bytes("ab\\x4"),
+            Err(Error::InvalidEscape { position: 2 })
+        ));
+        assert!(matches!(
+            escaped_to_bytes("ab\\x+f"),
+            Err(Error::InvalidEscape { position: 2 })
+        ));
+        assert!(matches!(
+            escaped_to_bytes("ab\\q"),
+            Err(Error::InvalidEscape { position: 2 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_variants() -> Result<(), Error> {
+        let input = b"\xfb\xff\xfe token?";
+
+        let standard = bytes_to_base64_with(input, Base64Variant::Standard)?;
+        assert_eq!(standard, "+//+IHRva2VuPw==");
+        let url_safe = bytes_to_base64_with(input, Base64Variant::UrlSafe)?;
+        assert_eq!(url_safe, "-__-IHRva2VuPw==");
+        let unpadded = bytes_to_base64_with(input, Base64Variant::UrlSafeNoPad)?;
+        assert_eq!(unpadded, "-__-IHRva2VuPw");
+
+        assert_eq!(base64_to_bytes(&standard)?, input);
+        assert_eq!(
+            base64_to_bytes_with(&url_safe, Base64Variant::UrlSafe)?,
+            input
+        );
+        assert_eq!(
+            base64_to_bytes_with(&unpadded, Base64Variant::UrlSafeNoPad)?,
+            input
+        );
+        assert_eq!(
+            base64_to_bytes_with(&url_safe, Base64Variant::UrlSafeNoPad)?,
+            input
+        );
+        assert!(matches!(
+            base64_to_bytes_with(&url_safe, Base64Variant::Standard),
+            Err(Error::InvalidBase64(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_mime() -> Result<(), Error> {
+        let input: Vec<u8> = (0..=255u8).collect();
+
+        let wrapped = bytes_to_base64_with(&input, Base64Variant::Mime)?;
+        assert!(wrapped.split("\r\n").all(|line| line.len() <= 76));
+        assert_eq!(wrapped.split("\r\n").count(), 5);
+
+        assert_eq!(base64_to_bytes_with(&wrapped, Base64Variant::Mime)?, input);
+        assert!(base64_to_bytes(&wrapped).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_stream() -> Result<(), Error> {
+        let input: Vec<u8> = (0..=255u8).cycle().take(5000).collect();
+        let wrapped = bytes_to_base64_with(&input, Base64Variant::Mime)?;
+
+        let decoded = base64_stream_to_bytes(wrapped.as_bytes(), Base64Variant::Mime)?;
+        assert_eq!(decoded, input);
+
+        let result = base64_stream_to_bytes(&b"AAAA\nAA!A"[..], Base64Variant::Mime);
+        assert!(matches!(result, Err(Error::InvalidBase64(_))));
+        Ok(())
+    }
 }
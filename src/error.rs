@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidHex { position: usize, found: char },
+    OddHexLength(usize),
+    InvalidBase64(base64::DecodeError),
+    InvalidUtf8(std::str::Utf8Error),
+    NotLatin1 { position: usize, found: char },
+    InvalidEscape { position: usize },
+    LengthMismatch { left: usize, right: usize },
+    InvalidKeySize(usize),
+    InvalidKey(&'static str),
+    InvalidKeyLenRange { start: usize, end: usize },
+    InputTooShort { needed: usize, got: usize },
+    KeystreamConflict { position: usize },
+    InvalidIvSize(usize),
+    InvalidBlockSize(usize),
+    NotBlockAligned { len: usize, block_size: usize },
+    InvalidPadding(PaddingError),
+    Io(io::Error),
+    InvalidRecord { line: usize, source: Box<Error> },
+    InvalidModel(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidHex { position, found } => {
+                write!(f, "invalid hex digit {:?} at position {}", found, position)
+            }
+            Error::OddHexLength(digits) => {
+                write!(f, "odd number of hex digits: {}", digits)
+            }
+            Error::InvalidBase64(e) => write!(f, "invalid base64: {}", e),
+            Error::InvalidUtf8(e) => write!(f, "invalid utf-8: {}", e),
+            Error::NotLatin1 { position, found } => write!(
+                f,
+                "character {:?} at position {} is outside latin-1",
+                found, position
+            ),
+            Error::InvalidEscape { position } => {
+                write!(f, "invalid escape sequence at position {}", position)
+            }
+            Error::LengthMismatch { left, right } => {
+                write!(f, "length mismatch: {} bytes vs {} bytes", left, right)
+            }
+            Error::InvalidKeySize(len) => write!(f, "invalid key size: {} bytes", len),
+            Error::InvalidKey(reason) => write!(f, "invalid key: {}", reason),
+            Error::InvalidKeyLenRange { start, end } => {
+                write!(f, "invalid key length range: {}..{}", start, end)
+            }
+            Error::KeystreamConflict { position } => {
+                write!(f, "conflicting keystream byte at position {}", position)
+            }
+            Error::InputTooShort { needed, got } => write!(
+                f,
+                "input too short: need at least {} bytes, got {}",
+                needed, got
+            ),
+            Error::InvalidIvSize(len) => write!(f, "invalid IV size: {} bytes", len),
+            Error::InvalidBlockSize(len) => write!(f, "invalid block size: {} bytes", len),
+            Error::NotBlockAligned { len, block_size } => write!(
+                f,
+                "input of {} bytes is not a multiple of the {} byte block size",
+                len, block_size
+            ),
+            Error::InvalidPadding(reason) => write!(f, "invalid padding: {}", reason),
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+            Error::InvalidRecord { line, source } => write!(f, "line {}: {}", line, source),
+            Error::InvalidModel(reason) => write!(f, "invalid model: {}", reason),
+        }
+    }
+}
+
+// Why padding was rejected. Kept separate from Error so padding oracles can
+// be modelled at whatever granularity a real implementation leaks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaddingError {
+    // Input is empty or not a whole number of blocks
+    BadLength(usize),
+    ZeroPadding,
+    // Padding length byte larger than the block size
+    OversizedPadding(u8),
+    // Byte at `position` doesn't match the padding length
+    MismatchedByte { position: usize },
+    // No end-of-data marker in the last block (ISO/IEC 7816-4)
+    MissingMarker,
+    // Rejected by a constant-time check, which doesn't say why
+    Rejected,
+}
+
+impl fmt::Display for PaddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaddingError::BadLength(len) => {
+                write!(f, "{} bytes is not a whole number of blocks", len)
+            }
+            PaddingError::ZeroPadding => write!(f, "padding length is zero"),
+            PaddingError::OversizedPadding(len) => {
+                write!(f, "padding length {} exceeds the block size", len)
+            }
+            PaddingError::MismatchedByte { position } => {
+                write!(f, "padding byte at position {} is wrong", position)
+            }
+            PaddingError::MissingMarker => write!(f, "no padding marker in the last block"),
+            PaddingError::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidBase64(e) => Some(e),
+            Error::InvalidUtf8(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::InvalidRecord { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(e: base64::DecodeError) -> Self {
+        Error::InvalidBase64(e)
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Error::InvalidUtf8(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        // Our own Read/Write adaptors (and the base64 ones we wrap) smuggle
+        // decode errors through io::Error, so hand those back as the
+        // original variant.
+        let inner = match e.get_ref() {
+            Some(inner) if inner.is::<Error>() || inner.is::<base64::DecodeError>() => {
+                e.into_inner()
+            }
+            _ => return Error::Io(e),
+        };
+        match inner.map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => match inner.downcast::<base64::DecodeError>() {
+                Ok(inner) => Error::InvalidBase64(*inner),
+                Err(_) => unreachable!("io::Error inner was checked to be a decode error"),
+            },
+            None => unreachable!("io::Error inner was checked to be present"),
+        }
+    }
+}
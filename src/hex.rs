@@ -0,0 +1,357 @@
+use std::io::{self, Read, Write};
+
+use crate::Error;
+
+const LOWER_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const UPPER_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Case {
+    Lower,
+    Upper,
+}
+
+// Separators are only accepted between whole bytes, never between the two
+// nibbles of a byte, so "de ad" decodes but "d ead" does not.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecodeOptions {
+    pub skip_whitespace: bool,
+    pub strip_prefix: bool,
+    pub allow_colons: bool,
+}
+
+impl DecodeOptions {
+    pub fn strict() -> Self {
+        DecodeOptions::default()
+    }
+
+    pub fn lenient() -> Self {
+        DecodeOptions {
+            skip_whitespace: true,
+            strip_prefix: true,
+            allow_colons: true,
+        }
+    }
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    encode_with_case(bytes, Case::Lower)
+}
+
+pub fn encode_upper(bytes: &[u8]) -> String {
+    encode_with_case(bytes, Case::Upper)
+}
+
+pub fn encode_with_case(bytes: &[u8], case: Case) -> String {
+    let mut output = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let [high, low] = encode_byte(*byte, case);
+        output.push(high as char);
+        output.push(low as char);
+    }
+    output
+}
+
+pub fn decode(input: &str) -> Result<Vec<u8>, Error> {
+    decode_with(input, DecodeOptions::strict())
+}
+
+pub fn decode_with(input: &str, options: DecodeOptions) -> Result<Vec<u8>, Error> {
+    let mut decoder = Decoder::new(options);
+    let mut output = Vec::with_capacity(input.len() / 2);
+
+    for (position, byte) in input.bytes().enumerate() {
+        match decoder.push(byte, position) {
+            Ok(Some(decoded)) => output.push(decoded),
+            Ok(None) => {}
+            Err(_) => {
+                // Every byte accepted so far was ASCII, so position is a char boundary
+                let found = input[position..].chars().next().unwrap_or('\u{fffd}');
+                return Err(Error::InvalidHex { position, found });
+            }
+        }
+    }
+    decoder.finish()?;
+
+    Ok(output)
+}
+
+pub fn encode_stream<R: Read, W: Write>(reader: R, writer: W, case: Case) -> Result<u64, Error> {
+    let mut reader = reader;
+    let mut hex_writer = HexWriter::new(writer, case);
+    let count = io::copy(&mut reader, &mut hex_writer)?;
+    hex_writer.flush()?;
+    Ok(count)
+}
+
+pub fn decode_stream<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    options: DecodeOptions,
+) -> Result<u64, Error> {
+    let mut writer = writer;
+    let mut hex_reader = HexReader::new(reader, options);
+    let count = io::copy(&mut hex_reader, &mut writer)?;
+    writer.flush()?;
+    Ok(count)
+}
+
+pub struct HexWriter<W: Write> {
+    inner: W,
+    case: Case,
+}
+
+impl<W: Write> HexWriter<W> {
+    pub fn new(inner: W, case: Case) -> Self {
+        HexWriter { inner, case }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for HexWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut encoded = Vec::with_capacity(buf.len() * 2);
+        for byte in buf {
+            encoded.extend_from_slice(&encode_byte(*byte, self.case));
+        }
+        self.inner.write_all(&encoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct HexReader<R: Read> {
+    inner: R,
+    decoder: Decoder,
+    position: usize,
+    scratch: Vec<u8>,
+    finished: bool,
+}
+
+impl<R: Read> HexReader<R> {
+    pub fn new(inner: R, options: DecodeOptions) -> Self {
+        HexReader {
+            inner,
+            decoder: Decoder::new(options),
+            position: 0,
+            scratch: vec![],
+            finished: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for HexReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.finished {
+            return Ok(0);
+        }
+
+        // Every output byte consumes at least two input bytes, so reading at
+        // most twice the caller's buffer can never overflow it.
+        self.scratch.resize(buf.len() * 2, 0);
+
+        loop {
+            let count = self.inner.read(&mut self.scratch)?;
+            if count == 0 {
+                self.finished = true;
+                self.decoder.finish().map_err(into_io_error)?;
+                return Ok(0);
+            }
+
+            let mut written = 0;
+            for byte in &self.scratch[..count] {
+                let decoded = self
+                    .decoder
+                    .push(*byte, self.position)
+                    .map_err(into_io_error)?;
+                self.position += 1;
+                if let Some(decoded) = decoded {
+                    buf[written] = decoded;
+                    written += 1;
+                }
+            }
+
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+fn into_io_error(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn encode_byte(byte: u8, case: Case) -> [u8; 2] {
+    let digits = match case {
+        Case::Lower => LOWER_DIGITS,
+        Case::Upper => UPPER_DIGITS,
+    };
+    [digits[(byte >> 4) as usize], digits[(byte & 0x0f) as usize]]
+}
+
+fn decode_nibble(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+struct Decoder {
+    options: DecodeOptions,
+    high: Option<u8>,
+    // A leading '0' at a byte boundary might be the start of a "0x" prefix
+    maybe_prefix: bool,
+    digits: usize,
+}
+
+impl Decoder {
+    fn new(options: DecodeOptions) -> Self {
+        Decoder {
+            options,
+            high: None,
+            maybe_prefix: false,
+            digits: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8, position: usize) -> Result<Option<u8>, Error> {
+        if let Some(nibble) = decode_nibble(byte) {
+            self.digits += 1;
+            return Ok(match self.high.take() {
+                Some(high) => {
+                    self.maybe_prefix = false;
+                    Some((high << 4) | nibble)
+                }
+                None => {
+                    self.high = Some(nibble);
+                    self.maybe_prefix = self.options.strip_prefix && byte == b'0';
+                    None
+                }
+            });
+        }
+
+        if self.maybe_prefix && (byte == b'x' || byte == b'X') {
+            self.high = None;
+            self.maybe_prefix = false;
+            self.digits -= 1;
+            return Ok(None);
+        }
+
+        let at_boundary = self.high.is_none();
+        if at_boundary
+            && ((self.options.skip_whitespace && byte.is_ascii_whitespace())
+                || (self.options.allow_colons && byte == b':'))
+        {
+            return Ok(None);
+        }
+
+        let found = if byte.is_ascii() {
+            byte as char
+        } else {
+            '\u{fffd}'
+        };
+        Err(Error::InvalidHex { position, found })
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        if self.high.is_some() {
+            return Err(Error::OddHexLength(self.digits));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"\x00\x7f\xde\xad"), "007fdead");
+        assert_eq!(encode_upper(b"\x00\x7f\xde\xad"), "007FDEAD");
+        assert_eq!(encode(b""), "");
+    }
+
+    #[test]
+    fn test_decode_strict() -> Result<(), Error> {
+        assert_eq!(decode("007fDEad")?, b"\x00\x7f\xde\xad");
+        assert!(matches!(decode("abc"), Err(Error::OddHexLength(3))));
+        assert!(matches!(
+            decode("ab cd"),
+            Err(Error::InvalidHex {
+                position: 2,
+                found: ' '
+            })
+        ));
+        // Multibyte characters must not cause a char boundary panic
+        assert!(matches!(
+            decode("abé0"),
+            Err(Error::InvalidHex {
+                position: 2,
+                found: 'é'
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_lenient() -> Result<(), Error> {
+        let options = DecodeOptions::lenient();
+        assert_eq!(
+            decode_with("0xde 0xad\n0xBE 0xef", options)?,
+            b"\xde\xad\xbe\xef"
+        );
+        assert_eq!(decode_with("de:ad:be:ef", options)?, b"\xde\xad\xbe\xef");
+        assert_eq!(decode_with("  00 0a\t", options)?, b"\x00\x0a");
+        assert!(matches!(
+            decode_with("d ead", options),
+            Err(Error::InvalidHex { position: 1, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_roundtrip() -> Result<(), Error> {
+        let input: Vec<u8> = (0..=255u8).cycle().take(10_000).collect();
+
+        let mut encoded: Vec<u8> = vec![];
+        let count = encode_stream(input.as_slice(), &mut encoded, Case::Upper)?;
+        assert_eq!(count, 10_000);
+        assert_eq!(encoded, encode_upper(&input).into_bytes());
+
+        let mut decoded: Vec<u8> = vec![];
+        let count = decode_stream(encoded.as_slice(), &mut decoded, DecodeOptions::strict())?;
+        assert_eq!(count, 10_000);
+        assert_eq!(decoded, input);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let mut decoded: Vec<u8> = vec![];
+        let result = decode_stream(&b"0011z2"[..], &mut decoded, DecodeOptions::strict());
+        assert!(matches!(
+            result,
+            Err(Error::InvalidHex {
+                position: 4,
+                found: 'z'
+            })
+        ));
+
+        let result = decode_stream(&b"001"[..], &mut decoded, DecodeOptions::strict());
+        assert!(matches!(result, Err(Error::OddHexLength(3))));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::fmt;
+
+const XXD_ROW_LEN: usize = 16;
+// 16 bytes as eight space separated groups of four hex digits
+const XXD_HEX_WIDTH: usize = 39;
+
+// Space separated lowercase hex, e.g. "de ad be ef"
+pub struct HexBytes<'a>(pub &'a [u8]);
+
+impl fmt::Display for HexBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+// xxd-style dump: offset, sixteen bytes in groups of two, then printable ASCII
+pub struct Hexdump<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Hexdump<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Hexdump { bytes }
+    }
+}
+
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, chunk) in self.bytes.chunks(XXD_ROW_LEN).enumerate() {
+            let mut hex = String::with_capacity(XXD_HEX_WIDTH);
+            for (i, byte) in chunk.iter().enumerate() {
+                if i > 0 && i % 2 == 0 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x}", byte));
+            }
+
+            let ascii: String = chunk
+                .iter()
+                .map(|c| {
+                    if c.is_ascii_graphic() || *c == b' ' {
+                        *c as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            writeln!(
+                f,
+                "{:08x}: {:<width$}  {}",
+                row * XXD_ROW_LEN,
+                hex,
+                ascii,
+                width = XXD_HEX_WIDTH
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// One row per cipher block. Blocks whose contents occur more than once are
+// tagged with a shared label so repeats (e.g. ECB) stand out.
+pub struct BlockView<'a> {
+    bytes: &'a [u8],
+    block_size: usize,
+}
+
+impl<'a> BlockView<'a> {
+    pub fn new(bytes: &'a [u8], block_size: usize) -> Self {
+        BlockView {
+            bytes,
+            block_size: block_size.max(1),
+        }
+    }
+
+    pub fn blocks(&self) -> std::slice::Chunks<'a, u8> {
+        self.bytes.chunks(self.block_size)
+    }
+
+    // Groups of block indices sharing identical contents, in order of first
+    // appearance. Only full blocks are considered.
+    pub fn duplicate_blocks(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let mut seen: HashMap<&[u8], usize> = HashMap::new();
+
+        for (i, block) in self.bytes.chunks_exact(self.block_size).enumerate() {
+            match seen.get(block) {
+                Some(group) => groups[*group].push(i),
+                None => {
+                    seen.insert(block, groups.len());
+                    groups.push(vec![i]);
+                }
+            }
+        }
+
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+}
+
+impl fmt::Display for BlockView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut labels: HashMap<usize, usize> = HashMap::new();
+        for (label, group) in self.duplicate_blocks().iter().enumerate() {
+            for index in group {
+                labels.insert(*index, label + 1);
+            }
+        }
+
+        for (i, block) in self.blocks().enumerate() {
+            write!(f, "{:>4}: {}", i, HexBytes(block))?;
+            if let Some(label) = labels.get(&i) {
+                let padding = 3 * (self.block_size - block.len());
+                write!(f, "{:padding$}  <- dup #{}", "", label, padding = padding)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Block-aligned comparison of two buffers. Identical blocks take one row,
+// differing blocks show both sides with carets under the changed bytes.
+pub struct BlockDiff<'a> {
+    left: &'a [u8],
+    right: &'a [u8],
+    block_size: usize,
+}
+
+impl<'a> BlockDiff<'a> {
+    pub fn new(left: &'a [u8], right: &'a [u8], block_size: usize) -> Self {
+        BlockDiff {
+            left,
+            right,
+            block_size: block_size.max(1),
+        }
+    }
+
+    pub fn differing_blocks(&self) -> Vec<usize> {
+        (0..self.num_blocks())
+            .filter(|i| self.block(self.left, *i) != self.block(self.right, *i))
+            .collect()
+    }
+
+    fn num_blocks(&self) -> usize {
+        let longest = self.left.len().max(self.right.len());
+        longest.div_ceil(self.block_size)
+    }
+
+    fn block<'b>(&self, bytes: &'b [u8], index: usize) -> &'b [u8] {
+        let start = (index * self.block_size).min(bytes.len());
+        let end = (start + self.block_size).min(bytes.len());
+        &bytes[start..end]
+    }
+}
+
+impl fmt::Display for BlockDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..self.num_blocks() {
+            let left = self.block(self.left, i);
+            let right = self.block(self.right, i);
+            if left == right {
+                writeln!(f, "{:>4}  = {}", i, HexBytes(left))?;
+                continue;
+            }
+
+            writeln!(f, "{:>4}  < {}", i, render_partial(left, self.block_size))?;
+            writeln!(f, "      > {}", render_partial(right, self.block_size))?;
+
+            let carets: Vec<&str> = (0..self.block_size)
+                .map(|j| {
+                    if left.get(j) != right.get(j) {
+                        "^^"
+                    } else {
+                        "  "
+                    }
+                })
+                .collect();
+            writeln!(f, "        {}", carets.join(" ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+// Bytes missing from a short block are drawn as "--" so columns stay aligned
+fn render_partial(block: &[u8], block_size: usize) -> String {
+    let cells: Vec<String> = (0..block_size)
+        .map(|j| match block.get(j) {
+            Some(byte) => format!("{:02x}", byte),
+            None => String::from("--"),
+        })
+        .collect();
+    cells.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        let dump = Hexdump::new(b"Hello, world!\n\x00\x01\xffabc").to_string();
+        assert_eq!(
+            dump,
+            "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210a 0001  Hello, world!...\n\
+             00000010: ff61 6263                                .abc\n"
+        );
+        assert_eq!(Hexdump::new(b"").to_string(), "");
+    }
+
+    #[test]
+    fn test_block_view() {
+        let input = b"AAAABBBBAAAACCCCBBBBAAAAD";
+        let view = BlockView::new(input, 4);
+        assert_eq!(view.duplicate_blocks(), vec![vec![0, 2, 5], vec![1, 4]]);
+
+        let rendered = view.to_string();
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[0], "   0: 41 41 41 41  <- dup #1");
+        assert_eq!(rows[1], "   1: 42 42 42 42  <- dup #2");
+        assert_eq!(rows[3], "   3: 43 43 43 43");
+        assert_eq!(rows[6], "   6: 44");
+    }
+
+    #[test]
+    fn test_block_diff() {
+        let left = b"AAAABBBBCC";
+        let right = b"AAAABxBBC";
+        let diff = BlockDiff::new(left, right, 4);
+        assert_eq!(diff.differing_blocks(), vec![1, 2]);
+
+        let rendered = diff.to_string();
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows[0], "   0  = 41 41 41 41");
+        assert_eq!(rows[1], "   1  < 42 42 42 42");
+        assert_eq!(rows[2], "      > 42 78 42 42");
+        assert_eq!(rows[3], "           ^^");
+        assert_eq!(rows[5], "      > 43 -- -- --");
+        assert_eq!(rows[6], "           ^^");
+    }
+}
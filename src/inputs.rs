@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::conversions::{self, Base64Variant};
+use crate::hex::{self, DecodeOptions};
+use crate::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Hex,
+    Base64,
+    Raw,
+}
+
+pub fn load_blob<P: AsRef<Path>>(path: P, encoding: Encoding) -> Result<Vec<u8>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+
+    match encoding {
+        Encoding::Hex => {
+            let options = DecodeOptions {
+                skip_whitespace: true,
+                ..DecodeOptions::strict()
+            };
+            let mut output: Vec<u8> = vec![];
+            hex::HexReader::new(reader, options).read_to_end(&mut output)?;
+            Ok(output)
+        }
+        Encoding::Base64 => conversions::base64_stream_to_bytes(reader, Base64Variant::Mime),
+        Encoding::Raw => {
+            let mut reader = reader;
+            let mut output: Vec<u8> = vec![];
+            reader.read_to_end(&mut output)?;
+            Ok(output)
+        }
+    }
+}
+
+// One record per line. Blank lines are skipped and line endings are not part
+// of the record, so raw records cannot contain newlines.
+pub fn load_records<P: AsRef<Path>>(path: P, encoding: Encoding) -> Result<Vec<Vec<u8>>, Error> {
+    let contents = load_blob(path, Encoding::Raw)?;
+
+    let mut records: Vec<Vec<u8>> = vec![];
+    for (i, line) in contents.split(|c| *c == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = decode_record(line, encoding).map_err(|e| Error::InvalidRecord {
+            line: i + 1,
+            source: Box::new(e),
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn decode_record(line: &[u8], encoding: Encoding) -> Result<Vec<u8>, Error> {
+    match encoding {
+        Encoding::Hex => {
+            let mut output: Vec<u8> = vec![];
+            hex::HexReader::new(line.trim_ascii(), DecodeOptions::strict())
+                .read_to_end(&mut output)?;
+            Ok(output)
+        }
+        Encoding::Base64 => conversions::base64_to_bytes(line.trim_ascii()),
+        Encoding::Raw => Ok(Vec::from(line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_records() -> Result<(), Error> {
+        let records = load_records("./inputs/set1/4.txt", Encoding::Hex)?;
+        assert_eq!(records.len(), 327);
+        assert!(records.iter().all(|r| r.len() == 29 || r.len() == 30));
+
+        let records = load_records("./inputs/set1/8.txt", Encoding::Hex)?;
+        assert_eq!(records.len(), 204);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_blob() -> Result<(), Error> {
+        let blob = load_blob("./inputs/set1/7.txt", Encoding::Base64)?;
+        assert_eq!(blob.len() % 16, 0);
+
+        let raw = load_blob("./inputs/set1/7.txt", Encoding::Raw)?;
+        assert!(raw.len() > blob.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let result = load_blob("./inputs/set1/does_not_exist.txt", Encoding::Raw);
+        assert!(matches!(result, Err(Error::Io(_))));
+
+        // Base64 records are not valid hex
+        let result = load_records("./inputs/set1/6.txt", Encoding::Hex);
+        assert!(matches!(result, Err(Error::InvalidRecord { line: 1, .. })));
+    }
+}
@@ -1,4 +1,16 @@
+pub mod aes;
 pub mod block_ciphers;
+pub mod classical;
 pub mod conversions;
+mod error;
+pub mod hex;
+pub mod hexdump;
+pub mod inputs;
+pub mod padding;
+pub mod scoring;
+pub mod secret;
+pub mod stats;
 pub mod utility;
 pub mod xor;
+
+pub use error::{Error, PaddingError};
@@ -0,0 +1,273 @@
+use crate::utility;
+use crate::{Error, PaddingError};
+
+// Fills plaintext out to a whole number of blocks, and checks and removes
+// that fill after decryption. Block sizes are limited to 1..=255 bytes, since
+// several schemes store the padding length in a single byte.
+pub trait Padding {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error>;
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error>;
+}
+
+// n bytes of value n
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pkcs7;
+
+impl Padding for Pkcs7 {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        utility::pkcs7_padding(input, block_size)
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        utility::pkcs7_unpad(input, block_size)
+    }
+}
+
+// n - 1 zero bytes, then n
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnsiX923;
+
+impl Padding for AnsiX923 {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let padding = padding_len(input, block_size)?;
+        let mut output = Vec::from(input);
+        output.resize(input.len() + padding - 1, 0);
+        output.push(padding as u8);
+        Ok(output)
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let start = length_byte_start(input, block_size)?;
+        let last = input.len() - 1;
+        if let Some(offset) = input[start..last].iter().position(|c| *c != 0) {
+            return Err(Error::InvalidPadding(PaddingError::MismatchedByte {
+                position: start + offset,
+            }));
+        }
+        Ok(Vec::from(&input[..start]))
+    }
+}
+
+// n - 1 random bytes, then n. Only the length byte can be checked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Iso10126;
+
+impl Padding for Iso10126 {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let padding = padding_len(input, block_size)?;
+        let mut output = Vec::from(input);
+        output.extend((1..padding).map(|_| rand::random::<u8>()));
+        output.push(padding as u8);
+        Ok(output)
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let start = length_byte_start(input, block_size)?;
+        Ok(Vec::from(&input[..start]))
+    }
+}
+
+// ISO/IEC 7816-4 bit padding: a single 0x80, then zero bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Iso7816;
+
+impl Padding for Iso7816 {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let padding = padding_len(input, block_size)?;
+        let mut output = Vec::from(input);
+        output.push(0x80);
+        output.resize(input.len() + padding, 0);
+        Ok(output)
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        utility::check_padded_len(input, block_size)?;
+
+        // The marker is always in the last block
+        let block_start = input.len() - block_size;
+        for position in (block_start..input.len()).rev() {
+            match input[position] {
+                0x00 => continue,
+                0x80 => return Ok(Vec::from(&input[..position])),
+                _ => {
+                    return Err(Error::InvalidPadding(PaddingError::MismatchedByte {
+                        position,
+                    }))
+                }
+            }
+        }
+        Err(Error::InvalidPadding(PaddingError::MissingMarker))
+    }
+}
+
+// Zero bytes up to the next block boundary, none if already aligned. Unpad
+// strips every trailing zero, so plaintext that ends in zeros loses them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ZeroPadding;
+
+impl Padding for ZeroPadding {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        let padding = padding_len(input, block_size)? % block_size;
+        let mut output = Vec::from(input);
+        output.resize(input.len() + padding, 0);
+        Ok(output)
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        check_aligned(input, block_size)?;
+        let len = input.iter().rposition(|c| *c != 0).map_or(0, |i| i + 1);
+        Ok(Vec::from(&input[..len]))
+    }
+}
+
+// Input must already be block aligned, and is passed through untouched
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoPadding;
+
+impl Padding for NoPadding {
+    fn pad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        check_aligned(input, block_size)?;
+        Ok(Vec::from(input))
+    }
+
+    fn unpad(&self, input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+        check_aligned(input, block_size)?;
+        Ok(Vec::from(input))
+    }
+}
+
+// Bytes needed to reach the next block boundary, a whole block if aligned
+fn padding_len(input: &[u8], block_size: usize) -> Result<usize, Error> {
+    if block_size == 0 || block_size > 255 {
+        return Err(Error::InvalidBlockSize(block_size));
+    }
+    Ok(block_size - input.len() % block_size)
+}
+
+// For schemes ending in a length byte: checks it and returns where the
+// padding starts
+fn length_byte_start(input: &[u8], block_size: usize) -> Result<usize, Error> {
+    utility::check_padded_len(input, block_size)?;
+
+    let padding = input[input.len() - 1];
+    if padding == 0 {
+        return Err(Error::InvalidPadding(PaddingError::ZeroPadding));
+    }
+    if padding as usize > block_size {
+        return Err(Error::InvalidPadding(PaddingError::OversizedPadding(
+            padding,
+        )));
+    }
+    Ok(input.len() - padding as usize)
+}
+
+// Unlike the other schemes, zero and no padding allow empty input
+fn check_aligned(input: &[u8], block_size: usize) -> Result<(), Error> {
+    if block_size == 0 || block_size > 255 {
+        return Err(Error::InvalidBlockSize(block_size));
+    }
+    if !input.len().is_multiple_of(block_size) {
+        return Err(Error::NotBlockAligned {
+            len: input.len(),
+            block_size,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padding_vectors() -> Result<(), Error> {
+        let input = b"\xdd\xdd\xdd\xdd";
+        assert_eq!(Pkcs7.pad(input, 8)?, b"\xdd\xdd\xdd\xdd\x04\x04\x04\x04");
+        assert_eq!(AnsiX923.pad(input, 8)?, b"\xdd\xdd\xdd\xdd\x00\x00\x00\x04");
+        assert_eq!(Iso7816.pad(input, 8)?, b"\xdd\xdd\xdd\xdd\x80\x00\x00\x00");
+        assert_eq!(
+            ZeroPadding.pad(input, 8)?,
+            b"\xdd\xdd\xdd\xdd\x00\x00\x00\x00"
+        );
+
+        let iso10126 = Iso10126.pad(input, 8)?;
+        assert_eq!(iso10126.len(), 8);
+        assert_eq!(&iso10126[..4], input);
+        assert_eq!(iso10126[7], 4);
+
+        // Aligned input gets a whole block, except with zero and no padding
+        let aligned = [0xddu8; 8];
+        assert_eq!(AnsiX923.pad(&aligned, 8)?.len(), 16);
+        assert_eq!(Iso7816.pad(&aligned, 8)?.len(), 16);
+        assert_eq!(ZeroPadding.pad(&aligned, 8)?, aligned);
+        assert_eq!(NoPadding.pad(&aligned, 8)?, aligned);
+        Ok(())
+    }
+
+    #[test]
+    fn test_padding_round_trips() -> Result<(), Error> {
+        let schemes: [(&str, &dyn Padding); 5] = [
+            ("pkcs7", &Pkcs7),
+            ("x923", &AnsiX923),
+            ("iso10126", &Iso10126),
+            ("iso7816", &Iso7816),
+            ("zero", &ZeroPadding),
+        ];
+        for (name, scheme) in schemes {
+            for len in 0..=33 {
+                // Non-zero bytes, so zero padding round trips too
+                let input: Vec<u8> = (0..len).map(|i| i as u8 + 1).collect();
+                let padded = scheme.pad(&input, 16)?;
+                assert!(padded.len().is_multiple_of(16), "{} {}", name, len);
+                assert_eq!(scheme.unpad(&padded, 16)?, input, "{} {}", name, len);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpad_errors() {
+        let reason = |result: Result<Vec<u8>, Error>| match result {
+            Err(Error::InvalidPadding(reason)) => Some(reason),
+            _ => None,
+        };
+
+        assert_eq!(
+            reason(AnsiX923.unpad(b"\xdd\xdd\xdd\xdd\x00\x01\x00\x04", 8)),
+            Some(PaddingError::MismatchedByte { position: 5 })
+        );
+        assert_eq!(
+            reason(AnsiX923.unpad(b"\xdd\xdd\xdd\xdd\x00\x00\x00\x09", 8)),
+            Some(PaddingError::OversizedPadding(9))
+        );
+        assert_eq!(
+            reason(Iso10126.unpad(b"\xdd\xdd\xdd\xdd\x12\x34\x56\x00", 8)),
+            Some(PaddingError::ZeroPadding)
+        );
+        assert_eq!(
+            reason(Iso7816.unpad(b"\xdd\xdd\xdd\xdd\x80\x00\x01\x00", 8)),
+            Some(PaddingError::MismatchedByte { position: 6 })
+        );
+        assert_eq!(
+            reason(Iso7816.unpad(b"\x80\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00", 4)),
+            Some(PaddingError::MissingMarker)
+        );
+        assert_eq!(
+            reason(Iso7816.unpad(b"\xdd\xdd\xdd", 8)),
+            Some(PaddingError::BadLength(3))
+        );
+
+        assert!(matches!(
+            NoPadding.pad(b"\xdd\xdd\xdd", 8),
+            Err(Error::NotBlockAligned { len: 3, .. })
+        ));
+        assert!(matches!(
+            ZeroPadding.unpad(b"\xdd\xdd\xdd", 8),
+            Err(Error::NotBlockAligned { len: 3, .. })
+        ));
+        assert!(matches!(
+            AnsiX923.pad(b"", 256),
+            Err(Error::InvalidBlockSize(256))
+        ));
+    }
+}
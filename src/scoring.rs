@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::stats;
+use crate::utility::{self, BigramModel, ByteModel, DEFAULT_SMOOTHING};
+use crate::Error;
+
+// Scores how plausible a candidate plaintext is. Lower is always better, so
+// candidates from any scorer can be ranked with an ascending sort.
+pub trait Scorer {
+    fn score(&self, input: &[u8]) -> f32;
+}
+
+// Squared Hellinger distance between the input's byte frequencies and
+// English, as utility::EnglishScore
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrequencyDistance;
+
+impl Scorer for FrequencyDistance {
+    fn score(&self, input: &[u8]) -> f32 {
+        utility::EnglishScore::from(input).score
+    }
+}
+
+// Pearson's chi-squared statistic over all 256 byte values against the
+// smoothed English model, divided by the input length so inputs of different
+// lengths are comparable. Bytes English almost never uses have tiny expected
+// counts, so each one seen weighs heavily.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChiSquared;
+
+impl Scorer for ChiSquared {
+    fn score(&self, input: &[u8]) -> f32 {
+        if input.is_empty() {
+            return 0.0;
+        }
+
+        let len = input.len() as f32;
+        let english = utility::english_probabilities();
+        let counts = utility::count_bytes(input);
+
+        let mut total = 0.0;
+        for (count, p) in counts.iter().zip(english) {
+            let expected = p * len;
+            total += (*count as f32 - expected).powi(2) / expected;
+        }
+
+        total / len
+    }
+}
+
+// Mean negative log-probability of each byte under a unigram model, i.e. the
+// cross-entropy in nats per byte.
+#[derive(Clone, Debug)]
+pub struct LogLikelihood {
+    log_probs: [f32; 256],
+}
+
+impl LogLikelihood {
+    pub fn new(model: &ByteModel) -> Self {
+        let mut log_probs = [0.0; 256];
+        for (log_prob, p) in log_probs.iter_mut().zip(model.probabilities()) {
+            *log_prob = p.ln();
+        }
+        LogLikelihood { log_probs }
+    }
+
+    pub fn english() -> Self {
+        LogLikelihood::new(&ByteModel::english())
+    }
+}
+
+impl Scorer for LogLikelihood {
+    fn score(&self, input: &[u8]) -> f32 {
+        if input.is_empty() {
+            return 0.0;
+        }
+
+        let total: f32 = input.iter().map(|c| self.log_probs[*c as usize]).sum();
+        -total / input.len() as f32
+    }
+}
+
+// As LogLikelihood, but each byte is conditioned on the one before it
+#[derive(Clone, Debug)]
+pub struct BigramLikelihood {
+    first: LogLikelihood,
+    // log_probs[prev * 256 + next]
+    log_probs: Vec<f32>,
+}
+
+impl BigramLikelihood {
+    pub fn new(model: &BigramModel) -> Self {
+        let log_probs = (0..256 * 256)
+            .map(|i| model.probability((i / 256) as u8, (i % 256) as u8).ln())
+            .collect();
+        BigramLikelihood {
+            first: LogLikelihood::new(model.unigram()),
+            log_probs,
+        }
+    }
+}
+
+impl Scorer for BigramLikelihood {
+    fn score(&self, input: &[u8]) -> f32 {
+        if input.is_empty() {
+            return 0.0;
+        }
+
+        let first = self.first.score(&input[..1]);
+        let rest: f32 = input
+            .windows(2)
+            .map(|pair| self.log_probs[pair[0] as usize * 256 + pair[1] as usize])
+            .sum();
+        (first - rest) / input.len() as f32
+    }
+}
+
+// A few pages of public-domain English prose for NgramLikelihood::english
+const ENGLISH_CORPUS: &str = include_str!("data/english.txt");
+
+pub const MAX_NGRAM_ORDER: usize = 4;
+
+// Interpolation weight of each order's estimate, unigram first
+const NGRAM_WEIGHTS: [f32; MAX_NGRAM_ORDER] = [0.1, 0.2, 0.3, 0.4];
+
+// Interpolated n-gram model: the probability of each byte mixes the
+// estimates given the previous 0, 1, 2 and 3 bytes. Scores are the mean
+// negative log-probability per byte, like LogLikelihood, but runs of letters
+// that never occur in the language (anagram-like garbage) are penalized even
+// when their letter frequencies look right.
+//
+// Only orders whose context appeared in training take part, and their weights
+// are rescaled to sum to 1, so an unseen context hands its share down to the
+// lower orders (Jelinek-Mercer interpolation). The same happens for the first
+// bytes of an input, which have too little history for the higher orders.
+// The smoothed unigram model always takes part and keeps every byte finite.
+#[derive(Clone, Debug)]
+pub struct NgramLikelihood {
+    order: usize,
+    unigram: ByteModel,
+    // Index k - 2 maps each order k n-gram, packed big-endian into a u32, to
+    // the probability of its last byte given the rest
+    ngrams: Vec<HashMap<u32, f32>>,
+    // Index k - 2 holds the order k contexts (the first k - 1 bytes) seen in
+    // training
+    contexts: Vec<HashSet<u32>>,
+}
+
+impl NgramLikelihood {
+    pub fn new(sample: &[u8], order: usize, unigram: ByteModel) -> Result<Self, Error> {
+        if order == 0 || order > MAX_NGRAM_ORDER {
+            return Err(Error::InvalidModel("n-gram order must be between 1 and 4"));
+        }
+
+        let mut ngrams: Vec<HashMap<u32, f32>> = vec![];
+        let mut seen_contexts: Vec<HashSet<u32>> = vec![];
+        for k in 2..=order {
+            let mut counts: HashMap<u32, u32> = HashMap::new();
+            let mut contexts: HashMap<u32, u32> = HashMap::new();
+            for window in sample.windows(k) {
+                *counts.entry(pack(window)).or_insert(0) += 1;
+                *contexts.entry(pack(&window[..k - 1])).or_insert(0) += 1;
+            }
+
+            let probabilities = counts
+                .into_iter()
+                .map(|(key, count)| (key, count as f32 / contexts[&(key >> 8)] as f32))
+                .collect();
+            ngrams.push(probabilities);
+            seen_contexts.push(contexts.into_keys().collect());
+        }
+
+        Ok(NgramLikelihood {
+            order,
+            unigram,
+            ngrams,
+            contexts: seen_contexts,
+        })
+    }
+
+    pub fn train(sample: &[u8], order: usize) -> Result<Self, Error> {
+        NgramLikelihood::new(
+            sample,
+            order,
+            ByteModel::from_sample(sample, DEFAULT_SMOOTHING),
+        )
+    }
+
+    // Quadgrams from the bundled English sample, backed by ByteModel::english
+    pub fn english() -> Self {
+        NgramLikelihood::new(
+            ENGLISH_CORPUS.as_bytes(),
+            MAX_NGRAM_ORDER,
+            ByteModel::english(),
+        )
+        .expect("MAX_NGRAM_ORDER is a valid order")
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    // Probability of `byte` following `history`; only the last order - 1
+    // bytes of history are used. Sums to 1 over all bytes for any history.
+    pub fn probability(&self, history: &[u8], byte: u8) -> f32 {
+        let mut p = NGRAM_WEIGHTS[0] * self.unigram.probability(byte);
+        let mut total_weight = NGRAM_WEIGHTS[0];
+        for k in 2..=self.order.min(history.len() + 1) {
+            let context = pack(&history[history.len() - (k - 1)..]);
+            if !self.contexts[k - 2].contains(&context) {
+                continue;
+            }
+
+            total_weight += NGRAM_WEIGHTS[k - 1];
+            let key = (context << 8) | byte as u32;
+            if let Some(conditional) = self.ngrams[k - 2].get(&key) {
+                p += NGRAM_WEIGHTS[k - 1] * conditional;
+            }
+        }
+        p / total_weight
+    }
+}
+
+impl Scorer for NgramLikelihood {
+    fn score(&self, input: &[u8]) -> f32 {
+        if input.is_empty() {
+            return 0.0;
+        }
+
+        let total: f32 = (0..input.len())
+            .map(|i| self.probability(&input[..i], input[i]).ln())
+            .sum();
+        -total / input.len() as f32
+    }
+}
+
+fn pack(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u32)
+}
+
+// Fraction of bytes that are not printable ASCII. Cheap, and good at ruling
+// out garbage, but can't tell one printable candidate from another.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PrintableRatio;
+
+impl Scorer for PrintableRatio {
+    fn score(&self, input: &[u8]) -> f32 {
+        if input.is_empty() {
+            return 0.0;
+        }
+        1.0 - stats::printable_share(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGLISH: &[u8] = b"We hold these truths to be self-evident, that all men are created equal, that they are endowed by their Creator with certain unalienable Rights.";
+
+    #[test]
+    fn test_scorers_prefer_english() {
+        let shifted: Vec<u8> = ENGLISH.iter().map(|c| c ^ 0x01).collect();
+        let garbage: Vec<u8> = ENGLISH.iter().map(|c| c ^ 0x9c).collect();
+
+        let scorers: [(&str, Box<dyn Scorer>); 4] = [
+            ("frequency", Box::new(FrequencyDistance)),
+            ("chi-squared", Box::new(ChiSquared)),
+            ("log-likelihood", Box::new(LogLikelihood::english())),
+            ("printable", Box::new(PrintableRatio)),
+        ];
+        for (name, scorer) in &scorers {
+            let english = scorer.score(ENGLISH);
+            assert!(english < scorer.score(&garbage), "{}", name);
+            if *name != "printable" {
+                assert!(english < scorer.score(&shifted), "{}", name);
+            }
+        }
+
+        // The frequency scorers see every byte value, so rarer substitutes
+        // cost more rather than sharing one "other" bucket
+        let period = b"the c.t s.t .n the m.t";
+        let digits = b"the c4t s4t 0n the m4t";
+        let control = b"the c\x01t s\x01t \x01n the m\x01t";
+        for (name, scorer) in &scorers[..2] {
+            assert!(scorer.score(period) < scorer.score(digits), "{}", name);
+            assert!(scorer.score(digits) < scorer.score(control), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_trained_models() -> Result<(), crate::Error> {
+        use crate::xor;
+
+        // A model of Rust source, trained on a small fixed sample that
+        // doesn't contain the line being decrypted
+        let code = b"    for (i, byte) in dst.iter_mut().enumerate() {";
+        let bigrams = BigramModel::train("./src/data/rust_sample.txt", 0.5)?;
+        let scorer = BigramLikelihood::new(&bigrams);
+        assert!(scorer.score(code) < scorer.score(ENGLISH));
+
+        let ciphertext = xor::repeating_key_xor(code, &[0x42])?;
+        let (plaintext, key, _) = xor::recover_xor_key_with(&ciphertext, &scorer)?;
+        assert_eq!(key, 0x42);
+        assert_eq!(plaintext, code);
+
+        let unigrams = LogLikelihood::new(&ByteModel::train("./src/data/rust_sample.txt", 0.5)?);
+        let (_, key, _) = xor::recover_xor_key_with(&ciphertext, &unigrams)?;
+        assert_eq!(key, 0x42);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ngram_short_inputs() -> Result<(), Error> {
+        use crate::inputs::{self, Encoding};
+        use crate::xor;
+
+        // Challenge 4 with every line cut short. Unigram distance needs 6
+        // bytes before the English line outranks the other 326, while
+        // n-grams find it from 4.
+        let records = inputs::load_records("./inputs/set1/4.txt", Encoding::Hex)?;
+        let ngrams = NgramLikelihood::english();
+        for len in [4, 5] {
+            let truncated: Vec<&[u8]> = records.iter().map(|r| &r[..len]).collect();
+            let expected = &b"Now that the party is jumping"[..len];
+
+            let unigram = &xor::detect_single_byte_xor(&truncated)?[0];
+            assert_ne!(unigram.plaintext, expected, "{} bytes", len);
+
+            let ngram = &xor::detect_single_byte_xor_with(&truncated, &ngrams)?[0];
+            assert_eq!(ngram.key, 0x35, "{} bytes", len);
+            assert_eq!(ngram.plaintext, expected, "{} bytes", len);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ngram_model() -> Result<(), Error> {
+        let model = NgramLikelihood::train(b"the cat sat on the mat", 3)?;
+        assert_eq!(model.order(), 3);
+
+        // "th" was always followed by "e" in training
+        assert!(model.probability(b"th", b'e') > model.probability(b"th", b'a'));
+        assert!(model.probability(b"th", b'a') > 0.0);
+
+        // A distribution whatever the history: seen, unseen or too short
+        for history in [&b"th"[..], b"zq", b""] {
+            let total: f32 = (0..=255u8).map(|b| model.probability(history, b)).sum();
+            assert!(
+                (total - 1.0).abs() < 1e-4,
+                "{:?} sums to {}",
+                history,
+                total
+            );
+        }
+
+        // A context never seen in training falls back to the unigram model
+        let unigram = ByteModel::from_sample(b"the cat sat on the mat", DEFAULT_SMOOTHING);
+        assert!((model.probability(b"zq", b'a') - unigram.probability(b'a')).abs() < 1e-6);
+
+        // Same letters, scrambled into runs English never produces
+        let english = NgramLikelihood::english();
+        assert!(english.score(b"the hat on the mat") < english.score(b"hte aht no eht tam"));
+
+        assert!(matches!(
+            NgramLikelihood::train(b"abc", 5),
+            Err(Error::InvalidModel(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_printable_ratio() {
+        assert_eq!(PrintableRatio.score(b"abcd"), 0.0);
+        assert_eq!(PrintableRatio.score(b"ab\x00\xff"), 0.5);
+        assert_eq!(PrintableRatio.score(b""), 0.0);
+    }
+
+    #[test]
+    fn test_log_likelihood() {
+        let model = LogLikelihood::new(&ByteModel::from_sample(b"ab", 0.0));
+        assert!((model.score(b"abab") - 2f32.ln()).abs() < 1e-6);
+        assert_eq!(model.score(b"abc"), f32::INFINITY);
+
+        // Smoothing keeps unseen bytes finite, just unlikely
+        let model = LogLikelihood::new(&ByteModel::from_sample(b"ab", 0.5));
+        assert!(model.score(b"abc").is_finite());
+        assert!(model.score(b"abc") > model.score(b"abb"));
+    }
+}
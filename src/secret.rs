@@ -0,0 +1,205 @@
+use std::fmt;
+use std::hint::black_box;
+use std::io;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use crate::Error;
+
+// Key material. The bytes are zeroed when the Secret is dropped, Debug never
+// prints them, and == takes the same time wherever the inputs differ.
+//
+// Only the Secret's own buffer is wiped: a Vec handed to Secret::from may
+// have left copies behind when it grew, so build keys in place where it
+// matters (Secret::random, Secret::zeroed).
+#[derive(Clone)]
+pub struct Secret {
+    bytes: Vec<u8>,
+}
+
+impl Secret {
+    pub fn zeroed(len: usize) -> Self {
+        Secret {
+            bytes: vec![0; len],
+        }
+    }
+
+    // From the operating system's generator, which can fail (e.g. before
+    // the kernel pool is seeded); that's reported as Error::Io
+    pub fn random(len: usize) -> Result<Self, Error> {
+        let mut secret = Secret::zeroed(len);
+        OsRng
+            .try_fill_bytes(&mut secret.bytes)
+            .map_err(io::Error::from)?;
+        Ok(secret)
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(bytes: Vec<u8>) -> Self {
+        Secret { bytes }
+    }
+}
+
+impl From<&[u8]> for Secret {
+    fn from(bytes: &[u8]) -> Self {
+        Secret {
+            bytes: Vec::from(bytes),
+        }
+    }
+}
+
+impl AsRef<[u8]> for Secret {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsMut<[u8]> for Secret {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        wipe(&mut self.bytes);
+    }
+}
+
+// Volatile so the stores aren't removed as dead writes to memory that's
+// about to be freed
+pub(crate) fn wipe(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret([REDACTED; {} bytes])", self.bytes.len())
+    }
+}
+
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(&self.bytes, &other.bytes)
+    }
+}
+
+impl Eq for Secret {}
+
+// Compares every byte whatever the earlier ones were, so the time taken
+// doesn't reveal how long a matching prefix is. Lengths are treated as
+// public and a mismatch returns immediately.
+pub fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+
+    let diff = left
+        .iter()
+        .zip(right)
+        .fold(0u8, |acc, (l, r)| acc | black_box(l ^ r));
+    black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_secret() -> Result<(), Error> {
+        let key = Secret::from(&b"YELLOW SUBMARINE"[..]);
+        assert_eq!(format!("{:?}", key), "Secret([REDACTED; 16 bytes])");
+        assert!(!format!("{:?}", key).contains("YELLOW"));
+
+        assert_eq!(key, Secret::from(b"YELLOW SUBMARINE".to_vec()));
+        assert_ne!(key, Secret::from(&b"YELLOW SUBMARINE!"[..]));
+        assert_ne!(key, Secret::from(&b"YELLOW SUBMARINF"[..]));
+
+        let random = Secret::random(16)?;
+        assert_eq!(random.len(), 16);
+        assert_ne!(random, Secret::random(16)?);
+
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_wipe() {
+        let mut bytes = vec![0xaau8; 32];
+        wipe(&mut bytes);
+        assert!(bytes.iter().all(|b| *b == 0));
+    }
+
+    // Median time of `compare` over many runs
+    fn median_time<F: Fn() -> bool>(compare: F, samples: usize) -> Duration {
+        let mut times: Vec<Duration> = (0..samples)
+            .map(|_| {
+                let start = Instant::now();
+                black_box(compare());
+                start.elapsed()
+            })
+            .collect();
+        times.sort();
+        times[samples / 2]
+    }
+
+    // Wall-clock timing is at the mercy of machine load and of the other
+    // tests running in parallel, so this only runs on demand:
+    //   cargo test --release -- --ignored --test-threads=1 timing
+    #[test]
+    #[ignore]
+    fn test_constant_time_eq_timing() {
+        // Inputs that match entirely, differ in the first byte, and differ in
+        // the last. An early-exit comparison is much faster on the second.
+        const LEN: usize = 1 << 16;
+        const SAMPLES: usize = 101;
+        let reference = vec![0x5au8; LEN];
+        let equal = reference.clone();
+        let mut first = reference.clone();
+        first[0] ^= 1;
+        let mut last = reference.clone();
+        last[LEN - 1] ^= 1;
+
+        let naive = |other: &[u8]| black_box(&reference[..]) == black_box(other);
+        let ct = |other: &[u8]| constant_time_eq(black_box(&reference), black_box(other));
+
+        // Warm up caches and clocks before anything counts
+        for input in [&equal, &first, &last] {
+            median_time(|| ct(input), SAMPLES);
+        }
+
+        // Interleave the classes so drift in machine load hits them all alike
+        let mut naive_times = [Duration::ZERO; 3];
+        let mut ct_times = [Duration::ZERO; 3];
+        for _ in 0..3 {
+            for (i, input) in [&equal, &first, &last].iter().enumerate() {
+                naive_times[i] += median_time(|| naive(input), SAMPLES);
+                ct_times[i] += median_time(|| ct(input), SAMPLES);
+            }
+        }
+
+        // Sanity check that the measurement can see a leak at all
+        assert!(naive_times[1] * 4 < naive_times[0], "{:?}", naive_times);
+
+        let fastest = ct_times.iter().min().unwrap().as_secs_f64();
+        let slowest = ct_times.iter().max().unwrap().as_secs_f64();
+        assert!(slowest / fastest < 1.25, "{:?}", ct_times);
+    }
+}
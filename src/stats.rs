@@ -0,0 +1,281 @@
+use crate::hexdump::BlockView;
+use crate::utility::{self, ByteModel};
+
+// Upper 0.1% point of chi-squared with 255 degrees of freedom. Uniformly
+// random bytes only exceed this one time in a thousand.
+pub const UNIFORM_CHI_SQUARED_LIMIT: f32 = 330.52;
+
+// Random data of any useful length correlates far less than this
+const RANDOM_SERIAL_CORRELATION_LIMIT: f32 = 0.05;
+
+// Share of printable bytes above which a blob is treated as text
+const PLAINTEXT_PRINTABLE_SHARE: f32 = 0.95;
+
+// Leading bytes of common compressed containers
+const COMPRESSED_SIGNATURES: [&[u8]; 5] = [
+    b"\x1f\x8b",         // gzip
+    b"PK\x03\x04",       // zip
+    b"BZh",              // bzip2
+    b"\xfd7zXZ\x00",     // xz
+    b"\x28\xb5\x2f\xfd", // zstd
+];
+
+// Shannon entropy in bits per byte, from 0 (one repeated value) to 8. Inputs
+// shorter than 256 bytes can't reach 8 even when random.
+pub fn entropy(input: &[u8]) -> f32 {
+    let len = input.len() as f64;
+    let bits: f64 = utility::count_bytes(input)
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+    bits as f32
+}
+
+// Entropy of each block_size chunk, e.g. to find where a blob switches from
+// headers to compressed or encrypted data. The last block may be short.
+pub fn block_entropy(input: &[u8], block_size: usize) -> Vec<f32> {
+    input.chunks(block_size.max(1)).map(entropy).collect()
+}
+
+// Pearson's chi-squared statistic of the byte counts against equally likely
+// bytes. Compare with UNIFORM_CHI_SQUARED_LIMIT: good random data averages
+// 255, anything structured lands far above.
+pub fn chi_squared_uniform(input: &[u8]) -> f32 {
+    let counts = utility::count_bytes(input);
+    let expected = input.len() as f64 / 256.0;
+    if expected == 0.0 {
+        return 0.0;
+    }
+
+    let total: f64 = counts
+        .iter()
+        .map(|count| (*count as f64 - expected).powi(2) / expected)
+        .sum();
+    total as f32
+}
+
+// As chi_squared_uniform, but against the byte probabilities of `model`.
+// Models need smoothing, otherwise a byte they never saw makes this infinite.
+pub fn chi_squared_model(input: &[u8], model: &ByteModel) -> f32 {
+    let counts = utility::count_bytes(input);
+    let len = input.len() as f64;
+
+    let total: f64 = counts
+        .iter()
+        .zip(model.probabilities())
+        .map(|(count, p)| {
+            let expected = *p as f64 * len;
+            (*count as f64 - expected).powi(2) / expected
+        })
+        .sum();
+    total as f32
+}
+
+// Correlation between each byte and the next (wrapping around), between -1
+// and 1. Near zero for random data; structured data strays from zero in
+// either direction (English is slightly negative, as letters alternate with
+// spaces, while images and audio are strongly positive).
+// Constant input has no variance and is reported as perfectly correlated.
+pub fn serial_correlation(input: &[u8]) -> f32 {
+    if input.len() < 2 {
+        return 0.0;
+    }
+
+    let len = input.len() as f64;
+    let mut products = 0.0;
+    let mut sum = 0.0;
+    let mut squares = 0.0;
+    for (i, byte) in input.iter().enumerate() {
+        let x = *byte as f64;
+        let next = input[(i + 1) % input.len()] as f64;
+        products += x * next;
+        sum += x;
+        squares += x * x;
+    }
+
+    let denominator = len * squares - sum * sum;
+    if denominator == 0.0 {
+        return 1.0;
+    }
+    ((len * products - sum * sum) / denominator) as f32
+}
+
+pub fn printable_share(input: &[u8]) -> f32 {
+    if input.is_empty() {
+        return 0.0;
+    }
+
+    let printable = input
+        .iter()
+        .filter(|c| c.is_ascii_graphic() || matches!(c, b' ' | b'\n' | b'\r' | b'\t'))
+        .count();
+    printable as f32 / input.len() as f32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobKind {
+    Plaintext,
+    // A known container signature. Bare deflate and other headerless streams
+    // are statistically indistinguishable from random and are reported as such.
+    Compressed,
+    // Repeated ciphertext blocks
+    EcbCiphertext,
+    // Passes the uniformity and correlation tests: ciphertext from a good mode,
+    // key material, or compressed data without a header
+    Random,
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Triage {
+    pub kind: BlobKind,
+    pub entropy: f32,
+    pub index_of_coincidence: f32,
+    pub chi_squared: f32,
+    pub serial_correlation: f32,
+    pub printable_share: f32,
+    // Blocks identical to an earlier block
+    pub repeated_blocks: usize,
+}
+
+// Runs every test on `input` and makes a best guess at what it is.
+// `block_size` is used to look for ECB repeats; 16 for AES.
+pub fn triage(input: &[u8], block_size: usize) -> Triage {
+    let repeated_blocks = BlockView::new(input, block_size)
+        .duplicate_blocks()
+        .iter()
+        .map(|group| group.len() - 1)
+        .sum();
+
+    let mut result = Triage {
+        kind: BlobKind::Unknown,
+        entropy: entropy(input),
+        index_of_coincidence: utility::index_of_coincidence(input),
+        chi_squared: chi_squared_uniform(input),
+        serial_correlation: serial_correlation(input),
+        printable_share: printable_share(input),
+        repeated_blocks,
+    };
+
+    result.kind = if input.is_empty() {
+        BlobKind::Unknown
+    } else if is_compressed_container(input) {
+        BlobKind::Compressed
+    } else if result.printable_share >= PLAINTEXT_PRINTABLE_SHARE {
+        BlobKind::Plaintext
+    } else if result.repeated_blocks > 0 {
+        BlobKind::EcbCiphertext
+    } else if result.chi_squared < UNIFORM_CHI_SQUARED_LIMIT
+        && result.serial_correlation.abs() < RANDOM_SERIAL_CORRELATION_LIMIT
+    {
+        BlobKind::Random
+    } else {
+        BlobKind::Unknown
+    };
+
+    result
+}
+
+fn is_compressed_container(input: &[u8]) -> bool {
+    // zlib has no fixed magic, just a deflate method nibble and a header
+    // checksum that makes the first two bytes a multiple of 31
+    let zlib = input.len() >= 2
+        && input[0] & 0x0f == 8
+        && input[0] >> 4 <= 7
+        && (u16::from(input[0]) << 8 | u16::from(input[1])).is_multiple_of(31);
+
+    zlib || COMPRESSED_SIGNATURES
+        .iter()
+        .any(|magic| input.starts_with(magic))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{RngCore, SeedableRng};
+
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut output = vec![0u8; len];
+        StdRng::seed_from_u64(19).fill_bytes(&mut output);
+        output
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+
+        let all: Vec<u8> = (0..=255u8).collect();
+        assert_eq!(entropy(&all), 8.0);
+        assert!(entropy(&random_bytes(1 << 16)) > 7.99);
+
+        let mixed: Vec<u8> = [vec![b'A'; 32], all].concat();
+        assert_eq!(
+            block_entropy(&mixed, 32),
+            vec![0.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn test_chi_squared() {
+        let all: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        assert_eq!(chi_squared_uniform(&all), 0.0);
+        assert!(chi_squared_uniform(&random_bytes(1 << 16)) < UNIFORM_CHI_SQUARED_LIMIT);
+
+        let text = include_bytes!("data/english.txt");
+        assert!(chi_squared_uniform(text) > 100.0 * UNIFORM_CHI_SQUARED_LIMIT);
+
+        let english = ByteModel::english();
+        assert!(
+            chi_squared_model(text, &english) < chi_squared_model(&random_bytes(8851), &english)
+        );
+    }
+
+    #[test]
+    fn test_serial_correlation() {
+        assert!(serial_correlation(&random_bytes(1 << 16)).abs() < 0.01);
+        assert!(serial_correlation(include_bytes!("data/english.txt")) < -0.05);
+
+        let ramp: Vec<u8> = (0..=255u8).collect();
+        assert!(serial_correlation(&ramp) > 0.9);
+        let alternating: Vec<u8> = [0u8, 255].repeat(100);
+        assert_eq!(serial_correlation(&alternating), -1.0);
+        assert_eq!(serial_correlation(b"zzzz"), 1.0);
+    }
+
+    #[test]
+    fn test_triage() -> Result<(), crate::Error> {
+        use crate::inputs::{self, Encoding};
+
+        let text = include_bytes!("data/english.txt");
+        assert_eq!(triage(text, 16).kind, BlobKind::Plaintext);
+
+        let random = random_bytes(4096);
+        assert_eq!(triage(&random, 16).kind, BlobKind::Random);
+
+        let gzip = [&b"\x1f\x8b\x08\x00"[..], &random].concat();
+        assert_eq!(triage(&gzip, 16).kind, BlobKind::Compressed);
+        let zlib = [&b"\x78\x9c"[..], &random].concat();
+        assert_eq!(triage(&zlib, 16).kind, BlobKind::Compressed);
+
+        // Challenge 8: exactly one line is ECB
+        let records = inputs::load_records("./inputs/set1/8.txt", Encoding::Hex)?;
+        let ecb: Vec<usize> = records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| triage(r, 16).kind == BlobKind::EcbCiphertext)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(ecb, vec![132]);
+        assert_eq!(triage(&records[132], 16).repeated_blocks, 3);
+
+        assert_eq!(triage(b"", 16).kind, BlobKind::Unknown);
+        Ok(())
+    }
+}
@@ -1,3 +1,11 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::hex;
+use crate::inputs::{self, Encoding};
+use crate::{Error, PaddingError};
+
 pub fn hamming_distance_byte(left: u8, right: u8) -> usize {
     let differences = left ^ right;
     differences.count_ones() as usize
@@ -6,154 +14,438 @@ pub fn hamming_distance_byte(left: u8, right: u8) -> usize {
 pub fn hamming_distance<'a, T: IntoIterator<Item = &'a u8>>(
     left: T,
     right: T,
-) -> Result<usize, &'static str> {
+) -> Result<usize, Error> {
     let mut distance = 0;
 
-    for (lc, rc) in left.into_iter().zip(right.into_iter()) {
+    for (lc, rc) in left.into_iter().zip(right) {
         distance += hamming_distance_byte(*lc, *rc);
     }
 
     Ok(distance)
 }
 
-use std::collections::HashMap;
-pub fn gen_english_map() -> HashMap<u8, f32> {
-    HashMap::from([
-        (b'E', 0.111607),
-        (b'A', 0.084966),
-        (b'R', 0.075809),
-        (b'I', 0.075448),
-        (b'O', 0.071635),
-        (b'T', 0.069509),
-        (b'N', 0.066544),
-        (b'S', 0.057351),
-        (b'L', 0.054893),
-        (b'C', 0.045388),
-        (b'U', 0.036308),
-        (b'D', 0.033844),
-        (b'P', 0.031671),
-        (b'M', 0.030129),
-        (b'H', 0.030034),
-        (b'G', 0.024705),
-        (b'B', 0.020720),
-        (b'F', 0.018121),
-        (b'Y', 0.017779),
-        (b'W', 0.012899),
-        (b'K', 0.011016),
-        (b'V', 0.010074),
-        (b'X', 0.002902),
-        (b'Z', 0.002722),
-        (b'J', 0.001965),
-        (b'Q', 0.001962),
-        (b'*', 0.0),
-    ])
+// Probability that two bytes drawn without replacement are equal
+pub fn index_of_coincidence(input: &[u8]) -> f32 {
+    if input.len() < 2 {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for byte in input {
+        counts[*byte as usize] += 1;
+    }
+
+    let coincidences: usize = counts.iter().map(|n| n * n.saturating_sub(1)).sum();
+    coincidences as f32 / (input.len() * (input.len() - 1)) as f32
+}
+
+// Letter frequencies of running English text, in percent
+const ENGLISH_LETTERS: [(u8, f32); 26] = [
+    (b'a', 8.167),
+    (b'b', 1.492),
+    (b'c', 2.782),
+    (b'd', 4.253),
+    (b'e', 12.702),
+    (b'f', 2.228),
+    (b'g', 2.015),
+    (b'h', 6.094),
+    (b'i', 6.966),
+    (b'j', 0.153),
+    (b'k', 0.772),
+    (b'l', 4.025),
+    (b'm', 2.406),
+    (b'n', 6.749),
+    (b'o', 7.507),
+    (b'p', 1.929),
+    (b'q', 0.095),
+    (b'r', 5.987),
+    (b's', 6.327),
+    (b't', 9.056),
+    (b'u', 2.758),
+    (b'v', 0.978),
+    (b'w', 2.360),
+    (b'x', 0.150),
+    (b'y', 1.974),
+    (b'z', 0.074),
+];
+
+// Share of all bytes for everything that isn't a letter. Letters take what
+// is left over, split by ENGLISH_LETTERS and LOWERCASE_SHARE.
+const ENGLISH_SYMBOLS: [(u8, f32); 24] = [
+    (b' ', 0.165),
+    (b'\n', 0.012),
+    (b',', 0.011),
+    (b'.', 0.010),
+    (b'\'', 0.003),
+    (b'"', 0.003),
+    (b'-', 0.002),
+    (b'0', 0.0006),
+    (b'1', 0.0006),
+    (b'2', 0.0004),
+    (b'3', 0.0003),
+    (b'4', 0.0003),
+    (b'5', 0.0003),
+    (b'6', 0.0003),
+    (b'7', 0.0003),
+    (b'8', 0.0003),
+    (b'9', 0.0003),
+    (b'?', 0.0005),
+    (b'!', 0.0005),
+    (b';', 0.0003),
+    (b':', 0.0003),
+    (b'(', 0.0002),
+    (b')', 0.0002),
+    (b'\t', 0.0001),
+];
+
+// Any other printable ASCII byte
+const ENGLISH_RARE_SYMBOL: f32 = 0.00002;
+const LOWERCASE_SHARE: f32 = 0.96;
+
+// ByteModel::english is built as counts over a notional sample this large,
+// smoothed the same way as a model trained on real text
+const ENGLISH_SAMPLE_LEN: f32 = 1_000_000.0;
+pub const DEFAULT_SMOOTHING: f32 = 0.5;
+
+pub fn count_bytes(input: &[u8]) -> [u64; 256] {
+    let mut counts = [0u64; 256];
+    for byte in input {
+        counts[*byte as usize] += 1;
+    }
+    counts
+}
+
+// Probability of each of the 256 byte values. Models are built from counts
+// with additive smoothing, so bytes never seen in training still get a small
+// non-zero probability instead of a special-case penalty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByteModel {
+    counts: [u64; 256],
+    smoothing: f32,
+    probabilities: [f32; 256],
 }
 
-pub fn count_elem(input: &[u8], elem: u8) -> u32 {
-    let mut count = 0;
-    let upper_elem = elem.to_ascii_uppercase();
-
-    for item in input.iter() {
-        // item is ascii, potentially lower case
-        let upper_item = (*item).to_ascii_uppercase();
-        if upper_item == upper_elem {
-            count += 1;
-        } else if elem == b'*' {
-            if !(*item).is_ascii_alphabetic() && (*item != b' ') {
-                count += 1;
-            } else if (*item).is_ascii_control() {
-                //count = count + 10;
-                count += 100;
+impl ByteModel {
+    // Adds `smoothing` to every count before normalizing. With zero smoothing
+    // unseen bytes have probability zero.
+    pub fn from_counts(counts: &[u64; 256], smoothing: f32) -> Self {
+        let total = counts.iter().sum::<u64>() as f32 + 256.0 * smoothing;
+        let mut probabilities = [0.0; 256];
+        for (p, count) in probabilities.iter_mut().zip(counts) {
+            *p = (*count as f32 + smoothing) / total;
+        }
+        ByteModel {
+            counts: *counts,
+            smoothing,
+            probabilities,
+        }
+    }
+
+    pub fn from_sample(sample: &[u8], smoothing: f32) -> Self {
+        ByteModel::from_counts(&count_bytes(sample), smoothing)
+    }
+
+    // Trains on the raw bytes of any file: a corpus in another language,
+    // source code, logs, ...
+    pub fn train<P: AsRef<Path>>(path: P, smoothing: f32) -> Result<Self, Error> {
+        let sample = inputs::load_blob(path, Encoding::Raw)?;
+        Ok(ByteModel::from_sample(&sample, smoothing))
+    }
+
+    pub fn english() -> Self {
+        let mut shares = [0.0f32; 256];
+        for (byte, share) in ENGLISH_SYMBOLS {
+            shares[byte as usize] = share;
+        }
+        for byte in 0x20..0x7fu8 {
+            if byte.is_ascii_punctuation() && shares[byte as usize] == 0.0 {
+                shares[byte as usize] = ENGLISH_RARE_SYMBOL;
             }
         }
+
+        let letter_share = 1.0 - shares.iter().sum::<f32>();
+        for (letter, percent) in ENGLISH_LETTERS {
+            let share = letter_share * percent / 100.0;
+            shares[letter as usize] = share * LOWERCASE_SHARE;
+            shares[letter.to_ascii_uppercase() as usize] = share * (1.0 - LOWERCASE_SHARE);
+        }
+
+        let mut counts = [0u64; 256];
+        for (count, share) in counts.iter_mut().zip(shares) {
+            *count = (share * ENGLISH_SAMPLE_LEN).round() as u64;
+        }
+        ByteModel::from_counts(&counts, DEFAULT_SMOOTHING)
+    }
+
+    pub fn probability(&self, byte: u8) -> f32 {
+        self.probabilities[byte as usize]
     }
 
-    count
+    pub fn probabilities(&self) -> &[f32; 256] {
+        &self.probabilities
+    }
+
+    pub fn counts(&self) -> &[u64; 256] {
+        &self.counts
+    }
+
+    pub fn smoothing(&self) -> f32 {
+        self.smoothing
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let entries = (0..=255u8).map(|byte| (vec![byte], self.counts[byte as usize]));
+        write_model(writer, UNIGRAM_HEADER, self.smoothing, entries)
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let (smoothing, entries) = read_model(reader, UNIGRAM_HEADER, 1)?;
+        let mut counts = [0u64; 256];
+        for (key, count) in entries {
+            counts[key[0] as usize] += count;
+        }
+        Ok(ByteModel::from_counts(&counts, smoothing))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        ByteModel::read_from(BufReader::new(File::open(path)?))
+    }
 }
 
-use std::collections::HashSet;
-pub fn euclidean_freq_distance(left: &HashMap<u8, f32>, right: &HashMap<u8, f32>) -> f32 {
-    let left_keys: HashSet<u8> = left.keys().cloned().collect();
-    let right_keys: HashSet<u8> = right.keys().cloned().collect();
+// Probability of each byte given the byte before it, smoothed the same way
+// as ByteModel. The first byte of a text has no predecessor and is scored
+// with `unigram`, built from the same counts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigramModel {
+    // counts[prev * 256 + next]
+    counts: Vec<u64>,
+    row_totals: Vec<u64>,
+    smoothing: f32,
+    unigram: ByteModel,
+}
 
-    if left_keys != right_keys {
-        println!("Keys don't match!");
-        return 0.0;
+impl BigramModel {
+    // `counts` is indexed by prev * 256 + next
+    pub fn from_counts(counts: Vec<u64>, smoothing: f32) -> Result<Self, Error> {
+        if counts.len() != 256 * 256 {
+            return Err(Error::InvalidModel("bigram table must have 65536 counts"));
+        }
+
+        let row_totals: Vec<u64> = counts
+            .chunks_exact(256)
+            .map(|row| row.iter().sum())
+            .collect();
+        let mut unigram_counts = [0u64; 256];
+        unigram_counts.copy_from_slice(&row_totals);
+
+        Ok(BigramModel {
+            counts,
+            row_totals,
+            smoothing,
+            unigram: ByteModel::from_counts(&unigram_counts, smoothing),
+        })
+    }
+
+    pub fn from_sample(sample: &[u8], smoothing: f32) -> Self {
+        let mut counts = vec![0u64; 256 * 256];
+        for pair in sample.windows(2) {
+            counts[pair[0] as usize * 256 + pair[1] as usize] += 1;
+        }
+        BigramModel::from_counts(counts, smoothing).expect("table has 65536 entries")
+    }
+
+    pub fn train<P: AsRef<Path>>(path: P, smoothing: f32) -> Result<Self, Error> {
+        let sample = inputs::load_blob(path, Encoding::Raw)?;
+        Ok(BigramModel::from_sample(&sample, smoothing))
+    }
+
+    pub fn probability(&self, prev: u8, next: u8) -> f32 {
+        let count = self.counts[prev as usize * 256 + next as usize] as f32;
+        let total = self.row_totals[prev as usize] as f32;
+        (count + self.smoothing) / (total + 256.0 * self.smoothing)
+    }
+
+    pub fn unigram(&self) -> &ByteModel {
+        &self.unigram
     }
 
-    let mut accum = 0.0;
-    for (key, left_value) in left.iter() {
-        let right_value = right.get(key).unwrap();
-        accum += (left_value - right_value) * (left_value - right_value);
+    pub fn smoothing(&self) -> f32 {
+        self.smoothing
     }
 
-    accum
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let entries = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| (vec![(i / 256) as u8, (i % 256) as u8], *count));
+        write_model(writer, BIGRAM_HEADER, self.smoothing, entries)
+    }
+
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let (smoothing, entries) = read_model(reader, BIGRAM_HEADER, 2)?;
+        let mut counts = vec![0u64; 256 * 256];
+        for (key, count) in entries {
+            counts[key[0] as usize * 256 + key[1] as usize] += count;
+        }
+        BigramModel::from_counts(counts, smoothing)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        BigramModel::read_from(BufReader::new(File::open(path)?))
+    }
 }
 
-pub fn count_freq(input: &[u8]) -> HashMap<u8, f32> {
-    let mut output: HashMap<u8, f32> = HashMap::from([
-        (b'E', 0.),
-        (b'A', 0.),
-        (b'R', 0.),
-        (b'I', 0.),
-        (b'O', 0.),
-        (b'T', 0.),
-        (b'N', 0.),
-        (b'S', 0.),
-        (b'L', 0.),
-        (b'C', 0.),
-        (b'U', 0.),
-        (b'D', 0.),
-        (b'P', 0.),
-        (b'M', 0.),
-        (b'H', 0.),
-        (b'G', 0.),
-        (b'B', 0.),
-        (b'F', 0.),
-        (b'Y', 0.),
-        (b'W', 0.),
-        (b'K', 0.),
-        (b'V', 0.),
-        (b'X', 0.),
-        (b'Z', 0.),
-        (b'J', 0.),
-        (b'Q', 0.),
-        (b'*', 0.),
-    ]);
+// Saved models are text: a header line with the model kind and smoothing,
+// e.g. "unigram 0.5", then one "<hex bytes> <count>" line per non-zero count
+// ("65 1234" for a unigram, "7468 210" for the bigram "th"). Blank lines and
+// lines starting with '#' are ignored, so tables can be edited by hand.
+const UNIGRAM_HEADER: &str = "unigram";
+const BIGRAM_HEADER: &str = "bigram";
+
+// Key bytes and their count
+type ModelEntry = (Vec<u8>, u64);
+
+fn write_model<W, I>(mut writer: W, kind: &str, smoothing: f32, entries: I) -> Result<(), Error>
+where
+    W: Write,
+    I: Iterator<Item = ModelEntry>,
+{
+    writeln!(writer, "{} {}", kind, smoothing)?;
+    for (key, count) in entries.filter(|(_, count)| *count > 0) {
+        writeln!(writer, "{} {}", hex::encode(&key), count)?;
+    }
+    Ok(())
+}
 
-    let length = input.len() as f32;
+// Returns the smoothing and every (key, count) entry, where each key is
+// `key_len` bytes. Errors are wrapped in InvalidRecord with the line number.
+fn read_model<R: BufRead>(
+    reader: R,
+    kind: &str,
+    key_len: usize,
+) -> Result<(f32, Vec<ModelEntry>), Error> {
+    let mut smoothing: Option<f32> = None;
+    let mut entries: Vec<ModelEntry> = vec![];
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |e: Error| Error::InvalidRecord {
+            line: i + 1,
+            source: Box::new(e),
+        };
+        let (first, second) = line
+            .split_once(' ')
+            .ok_or(Error::InvalidModel("expected two fields"))
+            .map_err(invalid)?;
+
+        if smoothing.is_none() {
+            if first != kind {
+                return Err(invalid(Error::InvalidModel("unexpected model kind")));
+            }
+            let value = second
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|s| s.is_finite() && *s >= 0.0)
+                .ok_or(Error::InvalidModel("invalid smoothing"))
+                .map_err(invalid)?;
+            smoothing = Some(value);
+            continue;
+        }
+
+        let key = hex::decode(first).map_err(invalid)?;
+        if key.len() != key_len {
+            return Err(invalid(Error::InvalidModel("wrong number of bytes in key")));
+        }
+        let count = second
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| invalid(Error::InvalidModel("invalid count")))?;
+        entries.push((key, count));
+    }
+
+    let smoothing = smoothing.ok_or(Error::InvalidModel("missing header"))?;
+    Ok((smoothing, entries))
+}
+
+use std::sync::OnceLock;
+// ByteModel::english's probabilities. Every candidate scored goes through
+// here, so only build the model once.
+pub fn english_probabilities() -> &'static [f32; 256] {
+    static ENGLISH: OnceLock<[f32; 256]> = OnceLock::new();
+    ENGLISH.get_or_init(|| *ByteModel::english().probabilities())
+}
 
-    for (letter, val) in output.iter_mut() {
-        let new_val = (count_elem(input, *letter) as f32) / length;
-        *val = new_val;
+// Share of the input taken by each of the 256 byte values
+pub fn byte_frequencies(input: &[u8]) -> [f32; 256] {
+    let mut output = [0.0; 256];
+    if input.is_empty() {
+        return output;
     }
 
+    let length = input.len() as f32;
+    for (freq, count) in output.iter_mut().zip(count_bytes(input)) {
+        *freq = count as f32 / length;
+    }
     output
 }
 
+// Squared Hellinger distance: squared Euclidean distance between the square
+// roots of two byte distributions. A byte the model almost never sees, like a
+// control or high byte, costs close to its whole share of the input, while
+// the same share of a common letter costs little.
+pub fn hellinger_distance(left: &[f32; 256], right: &[f32; 256]) -> f32 {
+    left.iter()
+        .zip(right)
+        .map(|(l, r)| (l.sqrt() - r.sqrt()).powi(2))
+        .sum()
+}
+
 #[allow(dead_code)]
 pub struct EnglishScore {
     input_bytes: Vec<u8>,
-    input_freq: HashMap<u8, f32>,
-    ideal_freqs: HashMap<u8, f32>,
+    input_freq: [f32; 256],
+    ideal_freqs: &'static [f32; 256],
     pub score: f32,
 }
 
 #[allow(dead_code)]
 impl EnglishScore {
     pub fn from(input: &[u8]) -> Self {
+        let input_freq = byte_frequencies(input);
+        let ideal_freqs = english_probabilities();
+        let score = hellinger_distance(&input_freq, ideal_freqs);
         EnglishScore {
             input_bytes: Vec::from(input),
-            input_freq: count_freq(input),
-            ideal_freqs: gen_english_map(),
-            score: euclidean_freq_distance(&count_freq(input), &gen_english_map()),
+            input_freq,
+            ideal_freqs,
+            score,
         }
     }
 }
 
-pub fn pkcs7_padding(input: &[u8], block_size: usize) -> Result<Vec<u8>, &'static str> {
-    if block_size > 256 {
-        return Err("pkcs7 only valid for block sizes <= 256");
+pub fn pkcs7_padding(input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+    // Padding bytes hold the padding length, so blocks must be 1..=255 bytes
+    if block_size == 0 || block_size > 255 {
+        return Err(Error::InvalidBlockSize(block_size));
     }
 
     let mut ret_vec: Vec<u8> = Vec::from(input);
@@ -166,57 +458,211 @@ pub fn pkcs7_padding(input: &[u8], block_size: usize) -> Result<Vec<u8>, &'stati
     Ok(ret_vec)
 }
 
+// Strips PKCS#7 padding, checking every padding byte. Each way the padding
+// can be wrong is reported separately, which is exactly what a padding
+// oracle leaks; see pkcs7_unpad_constant_time for a version that doesn't.
+pub fn pkcs7_unpad(input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+    check_padded_len(input, block_size)?;
+
+    let padding = input[input.len() - 1];
+    if padding == 0 {
+        return Err(Error::InvalidPadding(PaddingError::ZeroPadding));
+    }
+    if padding as usize > block_size {
+        return Err(Error::InvalidPadding(PaddingError::OversizedPadding(
+            padding,
+        )));
+    }
+
+    let start = input.len() - padding as usize;
+    if let Some(offset) = input[start..].iter().position(|c| *c != padding) {
+        return Err(Error::InvalidPadding(PaddingError::MismatchedByte {
+            position: start + offset,
+        }));
+    }
+
+    Ok(Vec::from(&input[..start]))
+}
+
+// As pkcs7_unpad, but the padding checks take the same time and follow the
+// same path whatever the padding holds, and any failure is reported as
+// PaddingError::Rejected. Only the input length is allowed to affect timing.
+pub fn pkcs7_unpad_constant_time(input: &[u8], block_size: usize) -> Result<Vec<u8>, Error> {
+    check_padded_len(input, block_size)?;
+
+    let last_block = &input[input.len() - block_size..];
+    let padding = last_block[block_size - 1];
+
+    // All ones when the padding is bad, built without branching on it
+    let mut bad = ct_mask_eq(padding as u32, 0) | ct_mask_lt(block_size as u32, padding as u32);
+    for (distance, byte) in last_block.iter().rev().enumerate() {
+        let in_padding = ct_mask_lt(distance as u32, padding as u32);
+        bad |= in_padding & ct_mask_ne(*byte as u32, padding as u32);
+    }
+
+    if std::hint::black_box(bad) != 0 {
+        return Err(Error::InvalidPadding(PaddingError::Rejected));
+    }
+    Ok(Vec::from(&input[..input.len() - padding as usize]))
+}
+
+// Padded input is always at least one whole block. Lengths are public, so
+// they can be checked with ordinary branches.
+pub(crate) fn check_padded_len(input: &[u8], block_size: usize) -> Result<(), Error> {
+    if block_size == 0 || block_size > 255 {
+        return Err(Error::InvalidBlockSize(block_size));
+    }
+    if input.is_empty() || !input.len().is_multiple_of(block_size) {
+        return Err(Error::InvalidPadding(PaddingError::BadLength(input.len())));
+    }
+    Ok(())
+}
+
+// 0xff if a < b, otherwise 0. Both must be below 2^31.
+fn ct_mask_lt(a: u32, b: u32) -> u8 {
+    (a.wrapping_sub(b) >> 31) as u8 * 0xff
+}
+
+// 0xff if a != b, otherwise 0
+fn ct_mask_ne(a: u32, b: u32) -> u8 {
+    let diff = a ^ b;
+    ((diff | diff.wrapping_neg()) >> 31) as u8 * 0xff
+}
+
+fn ct_mask_eq(a: u32, b: u32) -> u8 {
+    !ct_mask_ne(a, b)
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
     #[test]
-    fn test_count_elem() -> Result<(), &'static str> {
-        let haystack: Vec<u8> = String::from("ASDFasdf").as_bytes().to_vec();
-        let result = count_elem(&haystack, b'a');
-        assert_eq!(result, 2);
-        let result2 = count_elem(&haystack, b'S');
-        assert_eq!(result2, 2);
-        let result3 = count_elem(&haystack, b'Q');
-        assert_eq!(result3, 0);
+    fn test_byte_frequencies() -> Result<(), &'static str> {
+        let result = byte_frequencies(b"AAAAAAA");
+        assert_eq!(result[b'A' as usize], 1.0);
+        let result = byte_frequencies(b"AAAAAAABBBBBBB");
+        assert_eq!(result[b'A' as usize], 0.5);
+        // Case, punctuation and control bytes all keep their own entries
+        let result = byte_frequencies(b"Aa|\x00\x00");
+        assert_eq!(result[b'A' as usize], 0.2);
+        assert_eq!(result[b'a' as usize], 0.2);
+        assert_eq!(result[0x00], 0.4);
+        assert_eq!(result[b'C' as usize], 0.0);
+        assert_eq!(byte_frequencies(b""), [0.0; 256]);
         Ok(())
     }
 
     #[test]
-    fn test_count_freq() -> Result<(), &'static str> {
-        let input1: Vec<u8> = String::from("AAAAAAA").as_bytes().to_vec();
-        let result1 = count_freq(&input1);
-        assert_eq!(result1.get(&(b'A')), Some(&1.0));
-        let input1: Vec<u8> = String::from("AAAAAAABBBBBBB").as_bytes().to_vec();
-        let result1 = count_freq(&input1);
-        assert_eq!(result1.get(&(b'A')), Some(&0.5));
-        let input1: Vec<u8> = String::from("AB|||").as_bytes().to_vec();
-        let result1 = count_freq(&input1);
-        assert_eq!(result1.get(&(b'A')), Some(&0.2));
-        assert_eq!(result1.get(&(b'C')), Some(&0.0));
+    fn test_hellinger_distance() -> Result<(), &'static str> {
+        let english = english_probabilities();
+        assert_eq!(hellinger_distance(english, english), 0.0);
+
+        let mut zeros = [0.0; 256];
+        zeros[0] = 1.0;
+        let mut ones = [0.0; 256];
+        ones[1] = 1.0;
+        assert_eq!(hellinger_distance(&zeros, &ones), 2.0);
         Ok(())
     }
 
     #[test]
-    fn test_euclidean_score() -> Result<(), &'static str> {
-        let left: Vec<u8> = String::from("We hold these truths to be self-evident, that all men are created equal, that they are endowed by their Creator with certain unalienable Rights, that among these are Life, Liberty and the pursuit of Happiness.").as_bytes().to_vec();
-        let left_freqs = count_freq(&left);
-        let right_freqs = gen_english_map();
-        let result = euclidean_freq_distance(&left_freqs, &right_freqs);
-        assert!((result - 0.009184015) < 0.01);
+    fn test_english_score() -> Result<(), &'static str> {
+        let input: Vec<u8> = String::from("We hold these truths to be self-evident, that all men are created equal, that they are endowed by their Creator with certain unalienable Rights, that among these are Life, Liberty and the pursuit of Happiness.").as_bytes().to_vec();
+        let object = EnglishScore::from(&input);
+        assert!(object.score < 0.1, "{}", object.score);
+
+        // Swapping letters for bytes English rarely uses costs more the rarer
+        // they are, rather than all landing in one "other" bucket
+        let score = |text: &[u8]| EnglishScore::from(text).score;
+        let text = b"the cat sat on the mat";
+        let digits = b"the c4t s4t 0n the m4t";
+        let period = b"the c.t s.t .n the m.t";
+        let control = b"the c\x01t s\x01t \x01n the m\x01t";
+        let high = b"the c\xe1t s\xe1t \xe1n the m\xe1t";
+        assert!(score(text) < score(period));
+        assert!(score(period) < score(digits));
+        assert!(score(digits) < score(control));
+        assert!((score(control) - score(high)).abs() < 1e-6);
         Ok(())
     }
 
     #[test]
-    fn test_english_score() -> Result<(), &'static str> {
-        let input: Vec<u8> = String::from("We hold these truths to be self-evident, that all men are created equal, that they are endowed by their Creator with certain unalienable Rights, that among these are Life, Liberty and the pursuit of Happiness.").as_bytes().to_vec();
-        let object = EnglishScore::from(&input);
-        println!("{}", object.score);
-        assert!((object.score - 0.01) < 0.001);
+    fn test_byte_model() -> Result<(), &'static str> {
+        let model = ByteModel::english();
+        let total: f32 = model.probabilities().iter().sum();
+        assert!((total - 1.0).abs() < 1e-4);
+
+        // Space, then e and t, are the most common bytes in English
+        let mut ranked: Vec<u8> = (0..=255u8).collect();
+        ranked.sort_by(|a, b| model.probability(*b).total_cmp(&model.probability(*a)));
+        assert_eq!(&ranked[..3], b" et");
+
+        assert!(model.probability(b'e') > model.probability(b'E'));
+        assert!(model.probability(b'.') > model.probability(b'~'));
+        assert!(model.probability(b'~') > model.probability(0x00));
+        assert!(model.probability(0x00) > 0.0);
+        assert_eq!(model.probability(0x00), model.probability(0xff));
+        assert_eq!(english_probabilities(), model.probabilities());
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_model_smoothing() -> Result<(), &'static str> {
+        let model = ByteModel::from_sample(b"aab", 1.0);
+        assert_eq!(model.probability(b'a'), 3.0 / 259.0);
+        assert_eq!(model.probability(b'b'), 2.0 / 259.0);
+        assert_eq!(model.probability(b'z'), 1.0 / 259.0);
+
+        let unsmoothed = ByteModel::from_sample(b"aab", 0.0);
+        assert_eq!(unsmoothed.probability(b'a'), 2.0 / 3.0);
+        assert_eq!(unsmoothed.probability(b'z'), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_model_persistence() -> Result<(), Error> {
+        let sample = b"the quick brown fox jumps over the lazy dog\n\x00\xff";
+
+        let model = ByteModel::from_sample(sample, 0.25);
+        let mut saved: Vec<u8> = vec![];
+        model.write_to(&mut saved)?;
+        assert!(saved.starts_with(b"unigram 0.25\n"));
+        assert_eq!(ByteModel::read_from(&saved[..])?, model);
+
+        let bigrams = BigramModel::from_sample(sample, 0.25);
+        let path = std::env::temp_dir().join(format!("cryptopals-bigram-{}", std::process::id()));
+        bigrams.save(&path)?;
+        let loaded = BigramModel::load(&path);
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded?, bigrams);
+
+        let expected = (2.0 + 0.25) / (2.0 + 256.0 * 0.25);
+        assert_eq!(bigrams.probability(b't', b'h'), expected);
         Ok(())
     }
 
+    #[test]
+    fn test_model_load_errors() {
+        let result = ByteModel::read_from(&b"# comment\nbigram 0.5\n"[..]);
+        assert!(matches!(result, Err(Error::InvalidRecord { line: 2, .. })));
+
+        let result = ByteModel::read_from(&b"unigram 0.5\n\n61 12\n6162 3\n"[..]);
+        assert!(matches!(result, Err(Error::InvalidRecord { line: 4, .. })));
+
+        let result = BigramModel::read_from(&b"bigram 0.5\nzz 1\n"[..]);
+        match result {
+            Err(Error::InvalidRecord { line: 2, source }) => {
+                assert!(matches!(*source, Error::InvalidHex { .. }))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let result = ByteModel::read_from(&b""[..]);
+        assert!(matches!(result, Err(Error::InvalidModel(_))));
+    }
+
     #[test]
     fn test_hamming_byte() -> Result<(), &'static str> {
         let left = 0xff;
@@ -253,6 +699,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_index_of_coincidence() -> Result<(), &'static str> {
+        assert_eq!(index_of_coincidence(b"AAAA"), 1.0);
+        assert_eq!(index_of_coincidence(b"ABCD"), 0.0);
+        assert_eq!(index_of_coincidence(b"AABB"), 4.0 / 12.0);
+        assert_eq!(index_of_coincidence(b"A"), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pkcs7_unpad() -> Result<(), Error> {
+        let input = b"ICE ICE BABY\x04\x04\x04\x04";
+        assert_eq!(pkcs7_unpad(input, 16)?, b"ICE ICE BABY");
+        assert_eq!(pkcs7_unpad_constant_time(input, 16)?, b"ICE ICE BABY");
+
+        for len in 0..=48 {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let padded = pkcs7_padding(&plaintext, 16)?;
+            assert_eq!(pkcs7_unpad(&padded, 16)?, plaintext);
+            assert_eq!(pkcs7_unpad_constant_time(&padded, 16)?, plaintext);
+        }
+
+        let cases: [(&[u8], PaddingError); 5] = [
+            (
+                b"ICE ICE BABY\x05\x05\x05\x05",
+                PaddingError::MismatchedByte { position: 11 },
+            ),
+            (
+                b"ICE ICE BABY\x01\x02\x03\x04",
+                PaddingError::MismatchedByte { position: 12 },
+            ),
+            (b"ICE ICE BABY\x04\x04\x04\x00", PaddingError::ZeroPadding),
+            (
+                b"ICE ICE BABY\x04\x04\x04\x11",
+                PaddingError::OversizedPadding(0x11),
+            ),
+            (b"ICE ICE BABY\x04\x04\x04", PaddingError::BadLength(15)),
+        ];
+        for (input, expected) in cases {
+            match pkcs7_unpad(input, 16) {
+                Err(Error::InvalidPadding(reason)) => assert_eq!(reason, expected),
+                other => panic!("unexpected {:?}", other),
+            }
+
+            let expected = match expected {
+                PaddingError::BadLength(_) => expected,
+                _ => PaddingError::Rejected,
+            };
+            match pkcs7_unpad_constant_time(input, 16) {
+                Err(Error::InvalidPadding(reason)) => assert_eq!(reason, expected),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        assert!(matches!(
+            pkcs7_unpad(b"", 16),
+            Err(Error::InvalidPadding(PaddingError::BadLength(0)))
+        ));
+        assert!(matches!(
+            pkcs7_unpad(input, 0),
+            Err(Error::InvalidBlockSize(0))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_pkcs7_padding() -> Result<(), &'static str> {
         let input = b"YELLOW SUBMARINE";
@@ -1,81 +1,981 @@
+use std::ops::Range;
+
+use crate::scoring::{FrequencyDistance, Scorer};
 use crate::utility;
-use crate::xor;
+use crate::Error;
 
-pub fn fixed_xor(left: &[u8], right: &[u8]) -> Result<Vec<u8>, &'static str> {
-    if left.len() != right.len() {
-        return Err("fixed_xor: vectors must be the same length");
-    }
+// Repeating keys are expanded to at least this many bytes so that
+// repeating_key_xor_into can work a whole word at a time.
+const EXPANDED_KEY_LEN: usize = 256;
+
+pub fn fixed_xor(left: &[u8], right: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::from(left);
+    xor_into(&mut output, right)?;
+    Ok(output)
+}
 
-    Ok((0..left.len()).map(|i| left[i] ^ right[i]).collect())
+pub fn repeating_key_xor(left: &[u8], right: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::from(left);
+    repeating_key_xor_into(&mut output, right)?;
+    Ok(output)
 }
 
-pub fn repeating_key_xor(left: &[u8], right: &[u8]) -> Result<Vec<u8>, &'static str> {
-    let keymat: Vec<u8> = (0..left.len()).map(|i| right[i % right.len()]).collect();
-    fixed_xor(left, &keymat)
+// dst ^= src, sixteen bytes at a time with a bytewise tail
+pub fn xor_into(dst: &mut [u8], src: &[u8]) -> Result<(), Error> {
+    if dst.len() != src.len() {
+        return Err(Error::LengthMismatch {
+            left: dst.len(),
+            right: src.len(),
+        });
+    }
+
+    let mut dst_words = dst.chunks_exact_mut(16);
+    let mut src_words = src.chunks_exact(16);
+    for (d, s) in (&mut dst_words).zip(&mut src_words) {
+        let d_word: [u8; 16] = (*d).try_into().expect("chunks_exact yields 16-byte chunks");
+        let s_word: [u8; 16] = s.try_into().expect("chunks_exact yields 16-byte chunks");
+        let word = u128::from_ne_bytes(d_word) ^ u128::from_ne_bytes(s_word);
+        d.copy_from_slice(&word.to_ne_bytes());
+    }
+    for (d, s) in dst_words
+        .into_remainder()
+        .iter_mut()
+        .zip(src_words.remainder())
+    {
+        *d ^= s;
+    }
+
+    Ok(())
 }
 
-pub fn recover_xor_key(input: &[u8]) -> Result<(Vec<u8>, char, f32), &'static str> {
-    let mut cur_score = f32::INFINITY;
-    let mut cur_key = 'A';
-    let mut cur_plaintext: Vec<u8> = Vec::new();
+pub fn repeating_key_xor_into(dst: &mut [u8], key: &[u8]) -> Result<(), Error> {
+    if key.is_empty() {
+        return Err(Error::InvalidKeySize(0));
+    }
+
+    // Repeat the key a whole number of times, so every chunk of dst starts at
+    // key offset zero and can go through xor_into
+    let repeats = EXPANDED_KEY_LEN.div_ceil(key.len());
+    let expanded = key.repeat(repeats);
+    for chunk in dst.chunks_mut(expanded.len()) {
+        let len = chunk.len();
+        xor_into(chunk, &expanded[..len])?;
+    }
+
+    Ok(())
+}
 
-    for key in (0 as char)..(0xff as char) {
-        //println!("Testing key {}", key as char);
-        let keymat: Vec<u8> = (0..input.len()).map(|_| key as u8).collect();
-        let plaintext = xor::fixed_xor(input, &keymat)?;
-        //println!("\tPlaintext: \"{}\"", String::from_utf8_lossy(&plaintext.clone()));
-        let score_object = utility::EnglishScore::from(&plaintext);
-        //println!("\tScore: {}", score_object.score);
-        if score_object.score < cur_score {
-            //println!("\tFound new best score! Prev: {}, New: {}", cur_score, score_object.score);
-            cur_score = score_object.score;
-            cur_key = key;
-            cur_plaintext = plaintext;
+// XORs dst in place with bytes pulled from `keystream`. Fails if the
+// keystream ends before dst does, leaving the processed prefix XORed.
+pub fn xor_keystream_into<I>(dst: &mut [u8], keystream: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = u8>,
+{
+    let mut keystream = keystream.into_iter();
+    for (i, byte) in dst.iter_mut().enumerate() {
+        match keystream.next() {
+            Some(k) => *byte ^= k,
+            None => {
+                return Err(Error::LengthMismatch {
+                    left: dst.len(),
+                    right: i,
+                })
+            }
         }
     }
-    Ok((cur_plaintext, cur_key, cur_score))
+    Ok(())
+}
+
+// Lazily XORs `input` with `keystream`, stopping when either runs out
+pub fn xor_keystream<'a, I>(input: &'a [u8], keystream: I) -> impl Iterator<Item = u8> + 'a
+where
+    I: IntoIterator<Item = u8>,
+    I::IntoIter: 'a,
+{
+    input.iter().zip(keystream).map(|(c, k)| c ^ k)
+}
+
+#[derive(Clone, Debug)]
+pub struct XorCandidate {
+    pub key: u8,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Tries every one of the 256 single-byte keys and returns the best `count`,
+// lowest (most English-like) score first.
+pub fn rank_xor_keys(input: &[u8], count: usize) -> Result<Vec<XorCandidate>, Error> {
+    rank_xor_keys_with(input, count, &FrequencyDistance)
+}
+
+pub fn rank_xor_keys_with(
+    input: &[u8],
+    count: usize,
+    scorer: &dyn Scorer,
+) -> Result<Vec<XorCandidate>, Error> {
+    let mut candidates: Vec<XorCandidate> = Vec::with_capacity(256);
+
+    for key in 0..=255u8 {
+        let plaintext = repeating_key_xor(input, &[key])?;
+        let score = scorer.score(&plaintext);
+        candidates.push(XorCandidate {
+            key,
+            plaintext,
+            score,
+        });
+    }
+
+    // Stable sort, so equal scores keep ascending key order
+    candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+    candidates.truncate(count);
+
+    Ok(candidates)
+}
+
+pub fn recover_xor_key(input: &[u8]) -> Result<(Vec<u8>, u8, f32), Error> {
+    recover_xor_key_with(input, &FrequencyDistance)
+}
+
+pub fn recover_xor_key_with(
+    input: &[u8],
+    scorer: &dyn Scorer,
+) -> Result<(Vec<u8>, u8, f32), Error> {
+    let best = rank_xor_keys_with(input, 1, scorer)?.remove(0);
+    Ok((best.plaintext, best.key, best.score))
+}
+
+#[derive(Clone, Debug)]
+pub struct DetectedXor {
+    pub index: usize,
+    pub key: u8,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+// Breaks every candidate with its best single-byte key and ranks them, most
+// English-like first. `index` is the candidate's position in `candidates`.
+pub fn detect_single_byte_xor<I, T>(candidates: I) -> Result<Vec<DetectedXor>, Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    detect_single_byte_xor_with(candidates, &FrequencyDistance)
+}
+
+pub fn detect_single_byte_xor_with<I, T>(
+    candidates: I,
+    scorer: &dyn Scorer,
+) -> Result<Vec<DetectedXor>, Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut detections: Vec<DetectedXor> = vec![];
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let (plaintext, key, score) = recover_xor_key_with(candidate.as_ref(), scorer)?;
+        detections.push(DetectedXor {
+            index,
+            key,
+            plaintext,
+            score,
+        });
+    }
+
+    detections.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+    Ok(detections)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyLenEstimator {
+    // Mean normalized Hamming distance between key_len sized blocks, lower is better
+    #[default]
+    HammingDistance,
+    // Mean index of coincidence of the key_len columns, higher is better
+    IndexOfCoincidence,
+    // Fraction of bytes equal to the byte key_len positions later, higher is better
+    Autocorrelation,
+}
+
+// `score` is the estimator's raw statistic; see KeyLenEstimator for which
+// direction is better. Rankings are always returned best first.
+#[derive(Clone, Debug)]
+pub struct KeyLenCandidate {
+    pub length: usize,
+    pub score: f32,
+}
+
+// Key lengths in [min_len, max_len), most likely first
+pub fn rank_key_lens(
+    input: &[u8],
+    min_len: usize,
+    max_len: usize,
+) -> Result<Vec<KeyLenCandidate>, Error> {
+    rank_key_lens_with(input, min_len, max_len, KeyLenEstimator::default())
 }
 
-pub fn recover_key_len(
+pub fn rank_key_lens_with(
     input: &[u8],
     min_len: usize,
     max_len: usize,
-) -> Result<usize, &'static str> {
-    #[derive(Debug)]
-    struct Result {
-        //distance: f32,
-        length: usize,
-        score: f32,
+    estimator: KeyLenEstimator,
+) -> Result<Vec<KeyLenCandidate>, Error> {
+    if min_len == 0 || min_len >= max_len {
+        return Err(Error::InvalidKeyLenRange {
+            start: min_len,
+            end: max_len,
+        });
     }
 
-    let mut result_vec: Vec<Result> = vec![];
+    // Every estimator needs at least two samples per key byte position
+    let needed = 2 * (max_len - 1);
+    if input.len() < needed {
+        return Err(Error::InputTooShort {
+            needed,
+            got: input.len(),
+        });
+    }
 
+    let mut result_vec: Vec<KeyLenCandidate> = vec![];
     for key_len in min_len..max_len {
-        let block1 = &input[0..key_len];
-        let block2 = &input[key_len..2 * key_len];
-        let block3 = &input[2 * key_len..3 * key_len];
-        let block4 = &input[3 * key_len..4 * key_len];
-        let distances: Vec<usize> = vec![
-            utility::hamming_distance(block1, block2).unwrap(),
-            utility::hamming_distance(block1, block3).unwrap(),
-            utility::hamming_distance(block1, block4).unwrap(),
-            utility::hamming_distance(block2, block3).unwrap(),
-            utility::hamming_distance(block2, block4).unwrap(),
-            utility::hamming_distance(block3, block4).unwrap(),
-        ];
-        let test_distance = distances.iter().sum::<usize>() as f32 / 6.0;
-        let score: f32 = test_distance / key_len as f32;
+        let score = match estimator {
+            KeyLenEstimator::HammingDistance => mean_block_distance(input, key_len),
+            KeyLenEstimator::IndexOfCoincidence => {
+                let columns = transpose(input, key_len);
+                let total: f32 = columns
+                    .iter()
+                    .map(|c| utility::index_of_coincidence(c))
+                    .sum();
+                total / key_len as f32
+            }
+            KeyLenEstimator::Autocorrelation => {
+                let matches = input
+                    .iter()
+                    .zip(&input[key_len..])
+                    .filter(|(a, b)| a == b)
+                    .count();
+                matches as f32 / (input.len() - key_len) as f32
+            }
+        };
 
-        let res = Result {
+        result_vec.push(KeyLenCandidate {
             length: key_len,
             score,
+        });
+    }
+
+    match estimator {
+        KeyLenEstimator::HammingDistance => {
+            result_vec.sort_by(|a, b| a.score.total_cmp(&b.score));
+        }
+        KeyLenEstimator::IndexOfCoincidence | KeyLenEstimator::Autocorrelation => {
+            result_vec.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+    }
+
+    Ok(result_vec)
+}
+
+// Mean Hamming distance in bits per byte over every pair of whole blocks.
+// Rather than comparing pairs, count how many blocks set each bit: with c of
+// n blocks setting it, c * (n - c) pairs differ there. That makes the exact
+// all-pairs mean linear in the input length.
+fn mean_block_distance(input: &[u8], key_len: usize) -> f32 {
+    let mut ones = vec![0u64; key_len * 8];
+    let mut blocks = 0u64;
+    for block in input.chunks_exact(key_len) {
+        for (position, byte) in block.iter().enumerate() {
+            for bit in 0..8 {
+                ones[position * 8 + bit] += u64::from((byte >> bit) & 1);
+            }
+        }
+        blocks += 1;
+    }
+
+    let total: u64 = ones.iter().map(|c| c * (blocks - c)).sum();
+    let pairs = blocks * (blocks - 1) / 2;
+    total as f32 / pairs as f32 / key_len as f32
+}
+
+pub fn recover_key_len(input: &[u8], min_len: usize, max_len: usize) -> Result<usize, Error> {
+    Ok(rank_key_lens(input, min_len, max_len)?[0].length)
+}
+
+// How many of the best-ranked key lengths break_repeating_key_xor fully decrypts
+const KEY_LEN_ATTEMPTS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct RepeatingXorBreak {
+    pub key: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub score: f32,
+}
+
+pub fn break_repeating_key_xor(
+    ciphertext: &[u8],
+    key_len_range: Range<usize>,
+) -> Result<RepeatingXorBreak, Error> {
+    break_repeating_key_xor_with(ciphertext, key_len_range, &FrequencyDistance)
+}
+
+pub fn break_repeating_key_xor_with(
+    ciphertext: &[u8],
+    key_len_range: Range<usize>,
+    scorer: &dyn Scorer,
+) -> Result<RepeatingXorBreak, Error> {
+    let key_lens = rank_key_lens(ciphertext, key_len_range.start, key_len_range.end)?;
+
+    let mut best: Option<RepeatingXorBreak> = None;
+    for candidate in key_lens.iter().take(KEY_LEN_ATTEMPTS) {
+        let mut key: Vec<u8> = vec![];
+        for column in transpose(ciphertext, candidate.length) {
+            let (_, key_byte, _) = recover_xor_key_with(&column, scorer)?;
+            key.push(key_byte);
+        }
+
+        // A multiple of the true length recovers the key repeated, which
+        // decrypts identically, so report it at its shortest period.
+        let key = Vec::from(&key[..shortest_period(&key)]);
+        let plaintext = repeating_key_xor(ciphertext, &key)?;
+        let score = scorer.score(&plaintext);
+
+        if best.as_ref().is_none_or(|b| score < b.score) {
+            best = Some(RepeatingXorBreak {
+                key,
+                plaintext,
+                score,
+            });
+        }
+    }
+
+    best.ok_or(Error::InvalidKeyLenRange {
+        start: key_len_range.start,
+        end: key_len_range.end,
+    })
+}
+
+// Column i holds every byte encrypted under key byte i
+pub(crate) fn transpose(input: &[u8], key_len: usize) -> Vec<Vec<u8>> {
+    (0..key_len)
+        .map(|i| input.iter().skip(i).step_by(key_len).copied().collect())
+        .collect()
+}
+
+pub(crate) fn shortest_period(key: &[u8]) -> usize {
+    (1..key.len())
+        .filter(|period| key.len().is_multiple_of(*period))
+        .find(|period| key.chunks(*period).all(|chunk| chunk == &key[..*period]))
+        .unwrap_or(key.len())
+}
+
+#[derive(Clone, Debug)]
+pub struct CribMatch {
+    pub offset: usize,
+    pub revealed: Vec<u8>,
+    pub score: f32,
+}
+
+// Slides `crib` across the XOR of two ciphertexts that share a keystream. At
+// each offset the crib is assumed to be one plaintext, which reveals the
+// other; offsets where that counterpart is entirely printable are returned,
+// most English-like first.
+pub fn crib_drag(xored: &[u8], crib: &[u8]) -> Result<Vec<CribMatch>, Error> {
+    crib_drag_with(xored, crib, &FrequencyDistance)
+}
+
+pub fn crib_drag_with(
+    xored: &[u8],
+    crib: &[u8],
+    scorer: &dyn Scorer,
+) -> Result<Vec<CribMatch>, Error> {
+    let mut matches: Vec<CribMatch> = vec![];
+    if crib.is_empty() || crib.len() > xored.len() {
+        return Ok(matches);
+    }
+
+    for offset in 0..=xored.len() - crib.len() {
+        let revealed = fixed_xor(&xored[offset..offset + crib.len()], crib)?;
+        if !revealed.iter().all(|c| is_printable(*c)) {
+            continue;
+        }
+
+        let score = scorer.score(&revealed);
+        matches.push(CribMatch {
+            offset,
+            revealed,
+            score,
+        });
+    }
+
+    matches.sort_by(|a, b| a.score.total_cmp(&b.score));
+    Ok(matches)
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyCribMatch {
+    pub offset: usize,
+    // Key bytes implied by the crib, starting at key index offset % key_len
+    pub key_fragment: Vec<u8>,
+    // Every ciphertext byte under those key positions, decrypted in order
+    pub revealed: Vec<u8>,
+    pub score: f32,
+}
+
+// Slides `crib` across a single repeating-key XOR ciphertext. Each offset
+// implies some key bytes; those are checked against every other position they
+// encrypt, and only offsets that decrypt all of them to printable text are kept.
+pub fn crib_drag_repeating(
+    ciphertext: &[u8],
+    crib: &[u8],
+    key_len: usize,
+) -> Result<Vec<KeyCribMatch>, Error> {
+    crib_drag_repeating_with(ciphertext, crib, key_len, &FrequencyDistance)
+}
+
+pub fn crib_drag_repeating_with(
+    ciphertext: &[u8],
+    crib: &[u8],
+    key_len: usize,
+    scorer: &dyn Scorer,
+) -> Result<Vec<KeyCribMatch>, Error> {
+    if key_len == 0 {
+        return Err(Error::InvalidKeySize(0));
+    }
+
+    let mut matches: Vec<KeyCribMatch> = vec![];
+    if crib.is_empty() || crib.len() > ciphertext.len() {
+        return Ok(matches);
+    }
+
+    'offsets: for offset in 0..=ciphertext.len() - crib.len() {
+        let mut keystream = PartialKeystream::new(key_len);
+        if keystream.lock(offset, ciphertext, crib).is_err() {
+            // A crib longer than the key contradicts itself at this offset
+            continue;
+        }
+
+        let mut revealed: Vec<u8> = vec![];
+        for plain in keystream.decrypt(ciphertext).into_iter().flatten() {
+            if !is_printable(plain) {
+                continue 'offsets;
+            }
+            revealed.push(plain);
+        }
+
+        let key_fragment: Vec<u8> = (offset..offset + crib.len().min(key_len))
+            .filter_map(|i| keystream.get(i))
+            .collect();
+        let score = scorer.score(&revealed);
+        matches.push(KeyCribMatch {
+            offset,
+            key_fragment,
+            revealed,
+            score,
+        });
+    }
+
+    matches.sort_by(|a, b| a.score.total_cmp(&b.score));
+    Ok(matches)
+}
+
+// A keystream recovered a few bytes at a time. Position i of a message is
+// encrypted with keystream byte i % len, so this covers both one-time pads
+// (len >= message length) and repeating keys (len = key length).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialKeystream {
+    bytes: Vec<Option<u8>>,
+}
+
+impl PartialKeystream {
+    pub fn new(len: usize) -> Self {
+        PartialKeystream {
+            bytes: vec![None; len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn known(&self) -> usize {
+        self.bytes.iter().filter(|b| b.is_some()).count()
+    }
+
+    pub fn get(&self, position: usize) -> Option<u8> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        self.bytes[position % self.bytes.len()]
+    }
+
+    // Locks in the keystream implied by `plaintext` appearing at `offset` of
+    // `ciphertext`. Nothing is changed if any implied byte contradicts one
+    // already locked; unlock it first to revise a guess.
+    pub fn lock(
+        &mut self,
+        offset: usize,
+        ciphertext: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(), Error> {
+        if offset + plaintext.len() > ciphertext.len() {
+            return Err(Error::LengthMismatch {
+                left: ciphertext.len(),
+                right: offset + plaintext.len(),
+            });
+        }
+
+        let keystream = fixed_xor(&ciphertext[offset..offset + plaintext.len()], plaintext)?;
+        self.lock_keystream(offset, &keystream)
+    }
+
+    pub fn lock_keystream(&mut self, offset: usize, keystream: &[u8]) -> Result<(), Error> {
+        if self.bytes.is_empty() {
+            return Err(Error::InvalidKeySize(0));
+        }
+
+        let mut updated = self.bytes.clone();
+        for (i, byte) in keystream.iter().enumerate() {
+            let position = offset + i;
+            let slot = &mut updated[position % self.bytes.len()];
+            match slot {
+                Some(existing) if existing != byte => {
+                    return Err(Error::KeystreamConflict { position });
+                }
+                _ => *slot = Some(*byte),
+            }
+        }
+
+        self.bytes = updated;
+        Ok(())
+    }
+
+    pub fn unlock(&mut self, offset: usize, len: usize) {
+        if self.bytes.is_empty() {
+            return;
+        }
+        for position in offset..offset + len {
+            let slot = position % self.bytes.len();
+            self.bytes[slot] = None;
+        }
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Vec<Option<u8>> {
+        ciphertext
+            .iter()
+            .enumerate()
+            .map(|(i, c)| self.get(i).map(|k| c ^ k))
+            .collect()
+    }
+
+    // Unknown bytes are drawn as '_' and unprintable ones as '.'
+    pub fn render(&self, ciphertext: &[u8]) -> String {
+        self.decrypt(ciphertext)
+            .iter()
+            .map(|plain| match plain {
+                Some(c) if c.is_ascii_graphic() || *c == b' ' => *c as char,
+                Some(_) => '.',
+                None => '_',
+            })
+            .collect()
+    }
+}
+
+// Below this many contributing ciphertexts a column's byte frequencies say
+// little, so if the scorer's best key leaves an unprintable byte the column
+// falls back to character class.
+const MIN_SCORED_COLUMN: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct SharedKeystream {
+    pub keystream: Vec<u8>,
+    pub plaintexts: Vec<Vec<u8>>,
+}
+
+// Recovers a keystream reused across several ciphertexts (fixed-nonce CTR, a
+// reused one-time pad). Position i of the keystream is solved as a
+// single-byte XOR over byte i of every ciphertext long enough to have one,
+// taking the scorer's best key. Tail columns that only a few ciphertexts
+// reach can't be trusted to the scorer alone: there the key must also leave
+// every byte printable, else the one making the most letters and spaces wins.
+pub fn recover_shared_keystream<I, T>(ciphertexts: I) -> Result<SharedKeystream, Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    recover_shared_keystream_with(ciphertexts, &FrequencyDistance)
+}
+
+pub fn recover_shared_keystream_with<I, T>(
+    ciphertexts: I,
+    scorer: &dyn Scorer,
+) -> Result<SharedKeystream, Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let ciphertexts: Vec<T> = ciphertexts.into_iter().collect();
+    let longest = ciphertexts
+        .iter()
+        .map(|c| c.as_ref().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut keystream: Vec<u8> = Vec::with_capacity(longest);
+    for position in 0..longest {
+        let column: Vec<u8> = ciphertexts
+            .iter()
+            .filter_map(|c| c.as_ref().get(position).copied())
+            .collect();
+
+        let (plaintext, key, _) = recover_xor_key_with(&column, scorer)?;
+        let key = if column.len() >= MIN_SCORED_COLUMN || plaintext.iter().all(|c| is_printable(*c))
+        {
+            key
+        } else {
+            sparse_column_key(&column)
         };
-        result_vec.push(res);
+        keystream.push(key);
     }
 
-    use std::cmp::Ordering::Equal;
-    result_vec.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Equal));
-    result_vec.reverse();
+    let plaintexts = ciphertexts
+        .iter()
+        .map(|c| fixed_xor(c.as_ref(), &keystream[..c.as_ref().len()]))
+        .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+    Ok(SharedKeystream {
+        keystream,
+        plaintexts,
+    })
+}
+
+// Picks the key that turns the most bytes into letters or spaces, ruling out
+// any key that produces unprintable output if a printable one exists.
+fn sparse_column_key(column: &[u8]) -> u8 {
+    (0..=255u8)
+        .rev()
+        .max_by_key(|key| column_class_score(column, *key))
+        .unwrap_or(0)
+}
+
+fn column_class_score(column: &[u8], key: u8) -> i32 {
+    column
+        .iter()
+        .map(|c| match c ^ key {
+            p if p.is_ascii_alphabetic() || p == b' ' => 1,
+            p if is_printable(p) => 0,
+            _ => -(column.len() as i32) - 1,
+        })
+        .sum()
+}
+
+fn is_printable(byte: u8) -> bool {
+    byte.is_ascii_graphic() || matches!(byte, b' ' | b'\n' | b'\r' | b'\t')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(result_vec[0].length)
+    #[test]
+    fn test_xor_into() -> Result<(), Error> {
+        // Lengths either side of the 16 byte word size
+        for len in [0usize, 1, 15, 16, 17, 33, 1000] {
+            let left: Vec<u8> = (0..len).map(|i| (i * 31) as u8).collect();
+            let right: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            let expected: Vec<u8> = left.iter().zip(&right).map(|(l, r)| l ^ r).collect();
+
+            let mut dst = left.clone();
+            xor_into(&mut dst, &right)?;
+            assert_eq!(dst, expected);
+            assert_eq!(fixed_xor(&left, &right)?, expected);
+
+            let mut dst = left.clone();
+            xor_keystream_into(&mut dst, right.iter().copied())?;
+            assert_eq!(dst, expected);
+            let lazy: Vec<u8> = xor_keystream(&left, right.iter().copied()).collect();
+            assert_eq!(lazy, expected);
+        }
+
+        let mut dst = vec![0u8; 4];
+        assert!(matches!(
+            xor_into(&mut dst, &[1, 2, 3]),
+            Err(Error::LengthMismatch { left: 4, right: 3 })
+        ));
+        assert!(matches!(
+            xor_keystream_into(&mut dst, [1u8, 2]),
+            Err(Error::LengthMismatch { left: 4, right: 2 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeating_key_xor_into() -> Result<(), Error> {
+        let input: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        for key in [&b"X"[..], b"ICE", b"YELLOW SUBMARINE", &[0x5a; 300]] {
+            let expected: Vec<u8> = input
+                .iter()
+                .enumerate()
+                .map(|(i, c)| c ^ key[i % key.len()])
+                .collect();
+
+            let mut dst = input.clone();
+            repeating_key_xor_into(&mut dst, key)?;
+            assert_eq!(dst, expected);
+            assert_eq!(repeating_key_xor(&input, key)?, expected);
+        }
+
+        assert!(matches!(
+            repeating_key_xor(&input, b""),
+            Err(Error::InvalidKeySize(0))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_xor_keys() -> Result<(), Error> {
+        let plaintext = b"Cooking MC's like a pound of bacon";
+        for key in [0x00u8, 0x58, 0xff] {
+            let ciphertext = repeating_key_xor(plaintext, &[key])?;
+
+            let ranked = rank_xor_keys(&ciphertext, 5)?;
+            assert_eq!(ranked.len(), 5);
+            assert_eq!(ranked[0].key, key);
+            assert_eq!(ranked[0].plaintext, plaintext);
+            assert!(ranked.windows(2).all(|w| w[0].score <= w[1].score));
+
+            let (recovered, recovered_key, score) = recover_xor_key(&ciphertext)?;
+            assert_eq!(recovered, plaintext);
+            assert_eq!(recovered_key, key);
+            assert_eq!(score, ranked[0].score);
+        }
+
+        assert_eq!(rank_xor_keys(b"abc", 1000)?.len(), 256);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_xor_keys_with() -> Result<(), Error> {
+        use crate::scoring::{ChiSquared, LogLikelihood, PrintableRatio};
+
+        let plaintext = b"Cooking MC's like a pound of bacon";
+        let ciphertext = repeating_key_xor(plaintext, &[0x58])?;
+
+        let scorers: [&dyn Scorer; 3] =
+            [&ChiSquared, &LogLikelihood::english(), &FrequencyDistance];
+        for scorer in scorers {
+            let (recovered, key, _) = recover_xor_key_with(&ciphertext, scorer)?;
+            assert_eq!(key, 0x58);
+            assert_eq!(recovered, plaintext);
+        }
+
+        // Every case-flipped key leaves the text printable, so this scorer
+        // can only narrow the field
+        let ranked = rank_xor_keys_with(&ciphertext, 256, &PrintableRatio)?;
+        assert_eq!(ranked[0].score, 0.0);
+        assert!(ranked
+            .iter()
+            .take_while(|c| c.score == 0.0)
+            .any(|c| c.key == 0x58));
+        Ok(())
+    }
+
+    #[test]
+    fn test_break_repeating_key_xor() -> Result<(), Error> {
+        let plaintext = b"We hold these truths to be self-evident, that all men are created equal, that they are endowed by their Creator with certain unalienable Rights, that among these are Life, Liberty and the pursuit of Happiness. That to secure these rights, Governments are instituted among Men, deriving their just powers from the consent of the governed.";
+        let key = b"ICE ICE BABY";
+        let ciphertext = repeating_key_xor(plaintext, key)?;
+
+        let result = break_repeating_key_xor(&ciphertext, 2..20)?;
+        assert_eq!(result.key, key);
+        assert_eq!(result.plaintext, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_single_byte_xor() -> Result<(), Error> {
+        let hidden = repeating_key_xor(b"Now that the party is jumping", &[0x35])?;
+        let candidates: Vec<Vec<u8>> = vec![
+            repeating_key_xor(b"\x8f\x12\xa0\x33\xde\x01\x99\x7c", &[0x35])?,
+            hidden.clone(),
+            vec![0xaa; 29],
+        ];
+
+        let detections = detect_single_byte_xor(&candidates)?;
+        assert_eq!(detections.len(), 3);
+        assert_eq!(detections[0].index, 1);
+        assert_eq!(detections[0].key, 0x35);
+        assert_eq!(detections[0].plaintext, b"Now that the party is jumping");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_key_lens() -> Result<(), Error> {
+        use crate::inputs::{self, Encoding};
+
+        let ciphertext = inputs::load_blob("./inputs/set1/6.txt", Encoding::Base64)?;
+        for estimator in [
+            KeyLenEstimator::HammingDistance,
+            KeyLenEstimator::IndexOfCoincidence,
+            KeyLenEstimator::Autocorrelation,
+        ] {
+            let ranked = rank_key_lens_with(&ciphertext, 2, 41, estimator)?;
+            assert_eq!(ranked.len(), 39);
+            assert_eq!(ranked[0].length, 29, "{:?}", estimator);
+        }
+
+        // Same as comparing every pair of blocks directly, over the whole input
+        let blocks: Vec<&[u8]> = ciphertext.chunks_exact(7).collect();
+        let mut total = 0;
+        let mut pairs = 0;
+        for i in 0..blocks.len() {
+            for j in i + 1..blocks.len() {
+                total += utility::hamming_distance(blocks[i], blocks[j])?;
+                pairs += 1;
+            }
+        }
+        let expected = total as f32 / pairs as f32 / 7.0;
+        assert!((mean_block_distance(&ciphertext, 7) - expected).abs() < 1e-4);
+
+        assert!(matches!(
+            rank_key_lens(&ciphertext[..50], 2, 41),
+            Err(Error::InputTooShort {
+                needed: 80,
+                got: 50
+            })
+        ));
+        assert!(matches!(
+            rank_key_lens(&ciphertext, 5, 5),
+            Err(Error::InvalidKeyLenRange { start: 5, end: 5 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_crib_drag() -> Result<(), Error> {
+        let keystream: Vec<u8> = (0..64u8).map(|i| i.wrapping_mul(167) ^ 0x5a).collect();
+        let plain1 = b"Meet me at the usual place at ten";
+        let plain2 = b"The password is swordfish, repeat";
+        let cipher1 = fixed_xor(plain1, &keystream[..plain1.len()])?;
+        let cipher2 = fixed_xor(plain2, &keystream[..plain2.len()])?;
+        let xored = fixed_xor(&cipher1, &cipher2)?;
+
+        let matches = crib_drag(&xored, b" the ")?;
+        let hit = matches
+            .iter()
+            .find(|m| m.offset == 10)
+            .expect("crib offset");
+        assert_eq!(hit.revealed, &plain2[10..15]);
+        assert!(matches
+            .iter()
+            .all(|m| m.revealed.iter().all(|c| is_printable(*c))));
+
+        // Lock in the guess and the other plaintext starts to appear
+        let mut partial = PartialKeystream::new(cipher1.len());
+        partial.lock(10, &cipher1, b" the ")?;
+        assert_eq!(partial.known(), 5);
+        assert_eq!(
+            partial.render(&cipher2),
+            "__________rd is__________________"
+        );
+
+        assert!(matches!(
+            partial.lock(12, &cipher1, b"XX"),
+            Err(Error::KeystreamConflict { position: 12 })
+        ));
+        assert_eq!(partial.known(), 5);
+        partial.unlock(12, 2);
+        partial.lock(12, &cipher1, b"XX")?;
+        assert_eq!(partial.known(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crib_drag_repeating() -> Result<(), Error> {
+        let plaintext =
+            b"Attack the north gate at dawn and hold the bridge until the relief column arrives.";
+        let key = b"LEMONADE";
+        let ciphertext = repeating_key_xor(plaintext, key)?;
+
+        let matches = crib_drag_repeating(&ciphertext, b"the north", key.len())?;
+        assert_eq!(matches[0].offset, 7);
+        assert_eq!(matches[0].key_fragment, b"ELEMONAD");
+
+        let mut partial = PartialKeystream::new(key.len());
+        partial.lock(7, &ciphertext, b"the n")?;
+        assert_eq!(partial.known(), 5);
+        assert_eq!(partial.get(7 + key.len()), Some(key[7]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_shared_keystream() -> Result<(), Error> {
+        let plaintexts: Vec<&[u8]> = vec![
+            b"I have met them at close of day",
+            b"Coming with vivid faces",
+            b"From counter or desk among grey",
+            b"Eighteenth-century houses.",
+            b"I have passed with a nod of the head",
+            b"Or polite meaningless words,",
+            b"Or have lingered awhile and said",
+            b"Polite meaningless words,",
+            b"And thought before I had done",
+            b"Of a mocking tale or a gibe",
+            b"To please a companion",
+            b"Around the fire at the club,",
+            b"Being certain that they and I",
+            b"But lived where motley is worn:",
+            b"All changed, changed utterly:",
+            b"A terrible beauty is born.",
+        ];
+        let keystream: Vec<u8> = (0..36u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let ciphertexts: Vec<Vec<u8>> = plaintexts
+            .iter()
+            .map(|p| fixed_xor(p, &keystream[..p.len()]))
+            .collect::<Result<_, _>>()?;
+
+        let recovered = recover_shared_keystream(&ciphertexts)?;
+        assert_eq!(recovered.keystream.len(), 36);
+        assert_eq!(recovered.plaintexts.len(), plaintexts.len());
+
+        // Both models are case-sensitive and read the first column, all
+        // capitals, as lower case; columns shared by every line are
+        // otherwise exact
+        assert!(matches!(recovered.keystream[0] ^ keystream[0], 0x00 | 0x20));
+        assert_eq!(recovered.keystream[1..16], keystream[1..16]);
+
+        let correct = recovered
+            .plaintexts
+            .iter()
+            .zip(&plaintexts)
+            .flat_map(|(r, p)| r.iter().zip(p.iter()))
+            .filter(|(r, p)| r == p)
+            .count();
+        let total: usize = plaintexts.iter().map(|p| p.len()).sum();
+        assert!(correct * 100 >= total * 90, "{} of {}", correct, total);
+
+        let scorer = crate::scoring::LogLikelihood::english();
+        let recovered = recover_shared_keystream_with(&ciphertexts, &scorer)?;
+        assert!(matches!(recovered.keystream[0] ^ keystream[0], 0x00 | 0x20));
+        assert_eq!(recovered.keystream[1..16], keystream[1..16]);
+
+        // Columns a single line reaches still follow the scorer, which reads
+        // each lone byte as the commonest one, a space
+        let recovered = recover_shared_keystream_with([&ciphertexts[4][..]], &scorer)?;
+        assert!(recovered.plaintexts[0].iter().all(|c| *c == b' '));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shortest_period() {
+        assert_eq!(shortest_period(b"ICEICEICE"), 3);
+        assert_eq!(shortest_period(b"ICEICEIC"), 8);
+        assert_eq!(shortest_period(b"AAAA"), 1);
+        assert_eq!(shortest_period(b""), 0);
+    }
 }
@@ -1,8 +1,10 @@
 use cryptopals::conversions;
+use cryptopals::inputs::{self, Encoding};
 use cryptopals::xor;
+use cryptopals::Error;
 
 #[test]
-fn challenge_1() -> Result<(), &'static str> {
+fn challenge_1() -> Result<(), Error> {
     let hex_str = String::from("49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d");
     let base64_str =
         String::from("SSdtIGtpbGxpbmcgeW91ciBicmFpbiBsaWtlIGEgcG9pc29ub3VzIG11c2hyb29t");
@@ -20,7 +22,7 @@ fn challenge_1() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_2() -> Result<(), &'static str> {
+fn challenge_2() -> Result<(), Error> {
     let buf1 = match conversions::read_hexstr_as_bytes(
         String::from("1c0111001f010100061a024b53535009181c").as_ref(),
     ) {
@@ -45,7 +47,7 @@ fn challenge_2() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_3() -> Result<(), &'static str> {
+fn challenge_3() -> Result<(), Error> {
     let buf = match conversions::read_hexstr_as_bytes(
         String::from("1b37373331363f78151b7f2b783431333d78397828372d363c78373e783a393b3736")
             .as_ref(),
@@ -62,88 +64,34 @@ fn challenge_3() -> Result<(), &'static str> {
         "Final Plaintext: {}",
         conversions::bytes_to_str(&plaintext).unwrap()
     );
-    println!("Final Key:       {}", key);
+    println!("Final Key:       {}", key as char);
     println!("Final Score:     {}", score);
 
     Ok(())
 }
 
 #[test]
-fn challenge_4() -> Result<(), &'static str> {
-    use std::fs::File;
-    use std::io::BufRead;
-    use std::path::Path;
-
-    #[derive(Debug)]
-    struct Result {
-        input: Vec<u8>,
-        plaintext: Vec<u8>,
-        key: char,
-        score: f32,
-    }
-
-    let target_path = Path::new("./inputs/set1/4.txt");
-    let _display = target_path.display();
+fn challenge_4() -> Result<(), Error> {
+    let lines = inputs::load_records("./inputs/set1/4.txt", Encoding::Hex)?;
 
-    let file = match File::open(target_path) {
-        Err(_why) => {
-            panic!("Could not open file");
-        }
-        Ok(file) => file,
-    };
-
-    let bufreader = std::io::BufReader::new(file);
-
-    let mut res_vec: Vec<Result> = vec![];
-
-    for line in bufreader.lines() {
-        match line {
-            Ok(s) => {
-                match conversions::read_hexstr_as_bytes(&s) {
-                    Ok(bytes) => {
-                        //println!("Original String: \"{}\"", s);
-                        let (plaintext, key, score) = xor::recover_xor_key(&bytes)?;
-                        //let plainstr = bytes_to_str(plaintext.clone()).unwrap();
-                        //println!("\tFinal Plaintext: {}", plainstr);
-                        //println!("\tFinal Key:       {}", key as char);
-                        //println!("\tFinal Score:     {}", score);
-                        let res: Result = Result {
-                            input: bytes,
-                            plaintext,
-                            key,
-                            score,
-                        };
-                        res_vec.push(res);
-                    }
-                    Err(_err) => {
-                        println!("ParseIntErr!");
-                    }
-                }
-            }
-            Err(_err) => {
-                println!("Line read error?")
-            }
-        }
-    }
-    use std::cmp::Ordering::Equal;
-    res_vec.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Equal));
-    res_vec.reverse();
+    let res_vec = xor::detect_single_byte_xor(&lines)?;
 
     for (i, elem) in res_vec.iter().enumerate().take(1) {
-        println!("Top {} Result:", i + 1);
-        let cipherstr = String::from_utf8_lossy(&elem.input);
+        println!("Top {} Result (line {}):", i + 1, elem.index + 1);
+        let cipherstr = conversions::bytes_to_hex(&lines[elem.index])?;
         println!("\tCipherText:      {}", cipherstr);
         let plainstr = String::from_utf8_lossy(&elem.plaintext);
         println!("\tFinal Plaintext: {}", plainstr);
-        println!("\tFinal Key:       {}", elem.key);
-        println!("\tFinal Score:     {}", res_vec[i].score);
+        println!("\tFinal Key:       {}", elem.key as char);
+        println!("\tFinal Score:     {}", elem.score);
     }
+    assert_eq!(res_vec[0].plaintext, b"Now that the party is jumping\n");
 
     Ok(())
 }
 
 #[test]
-fn challenge_5() -> Result<(), &'static str> {
+fn challenge_5() -> Result<(), Error> {
     let plaintext = "Burning 'em, if you ain't quick and nimble\nI go crazy when I hear a cymbal";
     let key = "ICE";
     let ciphertext  = "0b3637272a2b2e63622c2e69692a23693a2a3c6324202d623d63343c2a26226324272765272a282b2f20430a652e2c652a3124333a653e2b2027630c692b20283165286326302e27282f";
@@ -158,112 +106,28 @@ fn challenge_5() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_6() -> Result<(), &'static str> {
-    use std::fs::File;
-    use std::io::BufRead;
-    use std::path::Path;
-
-    let target_path = Path::new("./inputs/set1/6.txt");
-    let _display = target_path.display();
-    let file = match File::open(target_path) {
-        Err(_why) => {
-            panic!("Could not open file");
-        }
-        Ok(file) => file,
-    };
-
-    let bufreader = std::io::BufReader::new(file);
+fn challenge_6() -> Result<(), Error> {
+    let file_bytes = inputs::load_blob("./inputs/set1/6.txt", Encoding::Base64)?;
 
-    let mut file_bytes: Vec<u8> = vec![];
-
-    for line in bufreader.lines() {
-        match line {
-            Ok(s) => match conversions::base64_to_bytes(s) {
-                Ok(mut bytes) => {
-                    file_bytes.append(&mut bytes);
-                }
-                Err(_err) => {
-                    println!("ParseIntErr!");
-                }
-            },
-            Err(_err) => {
-                println!("Line read error?")
-            }
-        }
-    }
-
-    let key_len = xor::recover_key_len(&file_bytes, 2, 40).unwrap();
-
-    // Break input into every key_len bytes
-    let mut blocks: Vec<Vec<u8>> = vec![];
-    let mut key_chars: Vec<u8> = vec![];
-
-    for i in 0..key_len {
-        // Construct blocks[i] out of every i'th byte
-        blocks.push(vec![]);
-        let mut block_iter = file_bytes.iter();
-
-        if i > 0 {
-            // Skip first elements to get stride right
-            for _ in 0..i {
-                block_iter.next();
-            }
-        }
-
-        let stride: Vec<u8> = block_iter.step_by(key_len).copied().collect();
-
-        let (_, key_char, _) = xor::recover_xor_key(&stride).unwrap();
-        key_chars.push(key_char as u8);
-    }
+    let result = xor::break_repeating_key_xor(&file_bytes, 2..40)?;
     println!(
         "Key found: {}",
-        conversions::bytes_to_str(&key_chars).unwrap()
+        conversions::bytes_to_str(&result.key).unwrap()
     );
+    assert_eq!(result.key, b"Terminator X: Bring the noise");
 
-    let plaintext = xor::repeating_key_xor(&file_bytes, &key_chars).unwrap();
-    let plainstr = conversions::bytes_to_str(&plaintext).unwrap();
+    let plainstr = conversions::bytes_to_str(&result.plaintext).unwrap();
     println!("Plaintext: {}", plainstr);
 
     Ok(())
 }
 
 #[test]
-fn challenge_7() -> Result<(), &'static str> {
+fn challenge_7() -> Result<(), Error> {
     let key = "YELLOW SUBMARINE";
     let keybytes = conversions::str_to_bytes(key).unwrap();
 
-    use std::fs::File;
-    use std::io::BufRead;
-    use std::path::Path;
-
-    let target_path = Path::new("./inputs/set1/7.txt");
-    let _display = target_path.display();
-    let file = match File::open(target_path) {
-        Err(_why) => {
-            panic!("Could not open file");
-        }
-        Ok(file) => file,
-    };
-
-    let bufreader = std::io::BufReader::new(file);
-
-    let mut file_bytes: Vec<u8> = vec![];
-
-    for line in bufreader.lines() {
-        match line {
-            Ok(s) => match conversions::base64_to_bytes(s) {
-                Ok(mut bytes) => {
-                    file_bytes.append(&mut bytes);
-                }
-                Err(_err) => {
-                    println!("ParseIntErr!");
-                }
-            },
-            Err(_err) => {
-                println!("Line read error?")
-            }
-        }
-    }
+    let file_bytes = inputs::load_blob("./inputs/set1/7.txt", Encoding::Base64)?;
 
     let plaintext = openssl::symm::decrypt(
         openssl::symm::Cipher::aes_128_ecb(),
@@ -281,39 +145,8 @@ fn challenge_7() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_8() -> Result<(), &'static str> {
-    use std::fs::File;
-    use std::io::BufRead;
-    use std::path::Path;
-
-    let target_path = Path::new("./inputs/set1/8.txt");
-    let _display = target_path.display();
-    let file = match File::open(target_path) {
-        Err(_why) => {
-            panic!("Could not open file");
-        }
-        Ok(file) => file,
-    };
-
-    let bufreader = std::io::BufReader::new(file);
-
-    let mut file_bytes: Vec<Vec<u8>> = vec![];
-
-    for line in bufreader.lines() {
-        match line {
-            Ok(s) => match conversions::read_hexstr_as_bytes(s.as_str()) {
-                Ok(bytes) => {
-                    file_bytes.push(bytes);
-                }
-                Err(_err) => {
-                    println!("ParseIntErr!");
-                }
-            },
-            Err(_err) => {
-                println!("Line read error?")
-            }
-        }
-    }
+fn challenge_8() -> Result<(), Error> {
+    let file_bytes = inputs::load_records("./inputs/set1/8.txt", Encoding::Hex)?;
 
     // Detect aes128ecb
     // Same 16B plaintext will result in same 16B ciphertext
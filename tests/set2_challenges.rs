@@ -1,8 +1,9 @@
 use block_ciphers::stable_ecb_oracle;
 use cryptopals::*;
+use hexdump::HexBytes;
 
 #[test]
-fn challenge_9() -> Result<(), &'static str> {
+fn challenge_9() -> Result<(), Error> {
     let input = b"YELLOW SUBMARINE";
     let output = b"YELLOW SUBMARINE\x04\x04\x04\x04";
 
@@ -19,39 +20,8 @@ fn challenge_9() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_10() -> Result<(), &'static str> {
-    use std::fs::File;
-    use std::io::BufRead;
-    use std::path::Path;
-
-    let target_path = Path::new("./inputs/set2/10.txt");
-    let _display = target_path.display();
-    let file = match File::open(target_path) {
-        Err(_why) => {
-            panic!("Could not open file");
-        }
-        Ok(file) => file,
-    };
-
-    let bufreader = std::io::BufReader::new(file);
-
-    let mut file_bytes: Vec<u8> = vec![];
-
-    for line in bufreader.lines() {
-        match line {
-            Ok(s) => match conversions::base64_to_bytes(s) {
-                Ok(mut bytes) => {
-                    file_bytes.append(&mut bytes);
-                }
-                Err(_err) => {
-                    println!("ParseIntErr!");
-                }
-            },
-            Err(_err) => {
-                println!("Line read error?")
-            }
-        }
-    }
+fn challenge_10() -> Result<(), Error> {
+    let file_bytes = inputs::load_blob("./inputs/set2/10.txt", inputs::Encoding::Base64)?;
 
     let key = b"YELLOW SUBMARINE";
     let iv = &[0u8; 16];
@@ -67,7 +37,7 @@ fn challenge_10() -> Result<(), &'static str> {
 }
 
 #[test]
-fn challenge_11() -> Result<(), &'static str> {
+fn challenge_11() -> Result<(), Error> {
     let _detected_type =
         crate::block_ciphers::ecb_detector(crate::block_ciphers::encryption_oracle).unwrap();
     dbg!(_detected_type);
@@ -79,7 +49,7 @@ fn vector_compare(va: &[u8], vb: &[u8]) -> bool {
 }
 
 use std::collections::HashMap;
-fn find_key_for_value(map: &HashMap<u8, Vec<u8>>, value: &Vec<u8>) -> Option<u8> {
+fn find_key_for_value(map: &HashMap<u8, Vec<u8>>, value: &[u8]) -> Option<u8> {
     map.iter().find_map(|(key, val)| {
         if vector_compare(val, value) {
             Some(*key)
@@ -91,8 +61,8 @@ fn find_key_for_value(map: &HashMap<u8, Vec<u8>>, value: &Vec<u8>) -> Option<u8>
 
 fn find_next_char(
     block_size: usize,
-    unknown_message: &String,
-    oracle: fn(&[u8]) -> Result<Vec<u8>, &'static str>,
+    unknown_message: &str,
+    oracle: fn(&[u8]) -> Result<Vec<u8>, Error>,
 ) -> Option<char> {
     // Identify required inputs
     let num_identified_chars = unknown_message.len();
@@ -113,18 +83,15 @@ fn find_next_char(
         full_oracle_input.push(ch as char);
         let start_idx = block_offset * block_size;
         let end_idx = start_idx + block_size;
-        let oracle_output = oracle(&full_oracle_input.as_bytes()).unwrap();
+        let oracle_output = oracle(full_oracle_input.as_bytes()).unwrap();
         let block_of_interest = Vec::from(&oracle_output[start_idx..end_idx]);
-        print!(
-            "\tPushing \'{}\' => ({}..{}) \'",
+        println!(
+            "\tPushing \'{}\' => ({}..{}) \'{}\'",
             &full_oracle_input[start_idx..end_idx],
             start_idx,
-            end_idx
+            end_idx,
+            HexBytes(&block_of_interest)
         );
-        for b in block_of_interest.iter() {
-            print!("{:02x} ", b);
-        }
-        println!("\'");
         oracle_dictionary.insert(ch, block_of_interest);
     }
 
@@ -135,17 +102,17 @@ fn find_next_char(
     let end_idx = start_idx + block_size;
     let block_of_interest = Vec::from(&oracle_output[start_idx..end_idx]);
     oracle_output.truncate(block_size);
-    print!("\tGot     \'{:15}\'  => \'", oracle_input);
-    for b in block_of_interest.iter() {
-        print!("{:02x} ", b);
-    }
-    println!("\'");
+    println!(
+        "\tGot     \'{:15}\'  => \'{}\'",
+        oracle_input,
+        HexBytes(&block_of_interest)
+    );
     if let Some(next_char) = find_key_for_value(&oracle_dictionary, &block_of_interest) {
-        print!("\tFound match            \'{}\' => \'", next_char as char);
-        for b in oracle_dictionary[&next_char].iter() {
-            print!("{:02x} ", b);
-        }
-        println!("");
+        println!(
+            "\tFound match            \'{}\' => \'{}\'",
+            next_char as char,
+            HexBytes(&oracle_dictionary[&next_char])
+        );
         Some(next_char as char)
     } else {
         println!("Could not find match, presumed end of message");
@@ -154,7 +121,7 @@ fn find_next_char(
 }
 
 #[test]
-fn challenge_12() -> Result<(), &'static str> {
+fn challenge_12() -> Result<(), Error> {
     let mut unknown_message = String::new();
     // 1. Feed identical bytes of your-string to the function 1 at a time --- start with 1 byte ("A"), then "AA", then "AAA" and so on. Discover the block size of the cipher.
     let block_size = crate::block_ciphers::detect_block_size(stable_ecb_oracle).unwrap();
@@ -208,9 +175,9 @@ fn kv_encoding(input_struct: &HashMap<String, String>) -> Result<String, &'stati
     let mut ret_str: String = String::new();
     for (k, v) in input_struct {
         ret_str.push_str(k.as_str());
-        ret_str.push_str("=");
+        ret_str.push('=');
         ret_str.push_str(v.as_str());
-        ret_str.push_str("&")
+        ret_str.push('&')
     }
     ret_str.pop(); // Remove trailing "&". TODO: find way to insert & only between tokens
     Ok(ret_str)
@@ -221,8 +188,8 @@ fn profile_for(emailaddr: String) -> Result<String, &'static String> {
     // First, escape all encoding metacharacters
     let sanitized_email = emailaddr.replace("&", "AND");
     let sanitized_email = sanitized_email.replace("=", "EQUALS");
-    static uid_gen: AtomicU32 = AtomicU32::new(0);
-    let uid = uid_gen.fetch_add(1, Ordering::Relaxed);
+    static UID_GEN: AtomicU32 = AtomicU32::new(0);
+    let uid = UID_GEN.fetch_add(1, Ordering::Relaxed);
     let role = String::from("user");
 
     let mut encoded_str = String::new();
@@ -240,7 +207,7 @@ fn profile_for(emailaddr: String) -> Result<String, &'static String> {
 fn challenge_13() -> Result<(), &'static String> {
 
     let input_str = "foo=bar&baz=qux&zap=zazzle";
-    let parsed_struct: HashMap<String, String> = kv_parsing(&input_str)?;
+    let parsed_struct: HashMap<String, String> = kv_parsing(input_str)?;
     let _encoded_str = kv_encoding(&parsed_struct)?;
 
     let encoded_profile = profile_for(String::from("foo@bar.com"))?; 